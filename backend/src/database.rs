@@ -24,6 +24,16 @@ pub struct Card {
     pub image_front_png: String,
     pub image_back_jpg: Option<String>,
     pub image_back_png: Option<String>,
+    pub mana_cost_front: Option<String>,
+    pub mana_cost_back: Option<String>,
+    pub type_line_front: Option<String>,
+    pub type_line_back: Option<String>,
+    pub oracle_text_front: Option<String>,
+    pub oracle_text_back: Option<String>,
+    pub power_front: Option<String>,
+    pub power_back: Option<String>,
+    pub toughness_front: Option<String>,
+    pub toughness_back: Option<String>,
     pub set: String,
     pub set_name: String,
     pub collector_number: String,
@@ -31,6 +41,14 @@ pub struct Card {
     pub preferred: bool,
 }
 
+/// Custom card image uploaded by a user, stored by the hash of its content
+#[derive(Serialize, FromRow, Clone)]
+pub struct Upload {
+    pub id: String,
+    pub name: String,
+    pub uploaded_at: DateTime<Utc>,
+}
+
 pub struct Database {
     pool: SqlitePool,
 }
@@ -69,6 +87,16 @@ impl Database {
                 image_front_png TEXT NOT NULL,
                 image_back_jpg TEXT,
                 image_back_png TEXT,
+                mana_cost_front TEXT,
+                mana_cost_back TEXT,
+                type_line_front TEXT,
+                type_line_back TEXT,
+                oracle_text_front TEXT,
+                oracle_text_back TEXT,
+                power_front TEXT,
+                power_back TEXT,
+                toughness_front TEXT,
+                toughness_back TEXT,
                 \"set\" TEXT,
                 set_name TEXT,
                 collector_number TEXT,
@@ -81,11 +109,44 @@ impl Database {
                 id INTEGER PRIMARY KEY,
                 last_updated TEXT
             )",
+            "CREATE TABLE IF NOT EXISTS uploads (
+                id TEXT PRIMARY KEY,
+                name TEXT NOT NULL,
+                image BLOB NOT NULL,
+                uploaded_at TEXT NOT NULL
+            )",
+            "CREATE INDEX IF NOT EXISTS uploads_name_idx
+                ON uploads (name)",
+        ];
+        // Columns added after the cards table was first created
+        static ADDED_COLUMNS: &[&str] = &[
+            "mana_cost_front",
+            "mana_cost_back",
+            "type_line_front",
+            "type_line_back",
+            "oracle_text_front",
+            "oracle_text_back",
+            "power_front",
+            "power_back",
+            "toughness_front",
+            "toughness_back",
         ];
         let mut tx = self.pool.begin().await?;
         for query in INITIALIZATION_QUERIES {
             sqlx::query(query).execute(&mut *tx).await?;
         }
+        let columns: Vec<String> =
+            sqlx::query_scalar("SELECT name FROM pragma_table_info('cards')")
+                .fetch_all(&mut *tx)
+                .await?;
+        for column in ADDED_COLUMNS {
+            if !columns.iter().any(|x| x == column) {
+                info!("Adding column {column} to cards table");
+                sqlx::query(&format!("ALTER TABLE cards ADD COLUMN {column} TEXT"))
+                    .execute(&mut *tx)
+                    .await?;
+            }
+        }
         tx.commit().await.context("failed to initialize db")
     }
 
@@ -151,15 +212,23 @@ impl Database {
                 "INSERT INTO cards
                     (id, name, flavor_name, normal_name_front, normal_name_back,
                         normal_flavor_name_front, normal_flavor_name_back, image_front_jpg,
-                        image_front_png, image_back_jpg, image_back_png, \"set\", set_name,
-                        collector_number, released_at, preferred)
-                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16)
+                        image_front_png, image_back_jpg, image_back_png, mana_cost_front,
+                        mana_cost_back, type_line_front, type_line_back, oracle_text_front,
+                        oracle_text_back, power_front, power_back, toughness_front,
+                        toughness_back, \"set\", set_name, collector_number, released_at,
+                        preferred)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16,
+                    $17, $18, $19, $20, $21, $22, $23, $24, $25, $26)
                 ON CONFLICT DO UPDATE SET
                     (id, name, flavor_name, normal_name_front, normal_name_back,
                             normal_flavor_name_front, normal_flavor_name_back, image_front_jpg,
-                            image_front_png, image_back_jpg, image_back_png, \"set\", set_name,
-                            collector_number, released_at, preferred)
-                    = ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16)",
+                            image_front_png, image_back_jpg, image_back_png, mana_cost_front,
+                            mana_cost_back, type_line_front, type_line_back, oracle_text_front,
+                            oracle_text_back, power_front, power_back, toughness_front,
+                            toughness_back, \"set\", set_name, collector_number, released_at,
+                            preferred)
+                    = ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16,
+                        $17, $18, $19, $20, $21, $22, $23, $24, $25, $26)",
             )
             .bind(&card.id)
             .bind(&card.name)
@@ -172,6 +241,16 @@ impl Database {
             .bind(&card.image_front_png)
             .bind(&card.image_back_jpg)
             .bind(&card.image_back_png)
+            .bind(&card.mana_cost_front)
+            .bind(&card.mana_cost_back)
+            .bind(&card.type_line_front)
+            .bind(&card.type_line_back)
+            .bind(&card.oracle_text_front)
+            .bind(&card.oracle_text_back)
+            .bind(&card.power_front)
+            .bind(&card.power_back)
+            .bind(&card.toughness_front)
+            .bind(&card.toughness_back)
             .bind(&card.set)
             .bind(&card.set_name)
             .bind(&card.collector_number)
@@ -183,6 +262,61 @@ impl Database {
         tx.commit().await.context("transaction failed")
     }
 
+    pub async fn get_upload(&self, id: &str) -> Result<Option<Upload>> {
+        sqlx::query_as(
+            "SELECT id, name, uploaded_at FROM uploads
+                WHERE id = $1",
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await
+        .context("failed to get upload")
+    }
+
+    pub async fn get_uploads_by_name(&self, name: &str) -> Result<Vec<Upload>> {
+        sqlx::query_as(
+            "SELECT id, name, uploaded_at FROM uploads
+                WHERE name = $1
+                ORDER BY uploaded_at DESC",
+        )
+        .bind(canonicalize_name(name))
+        .fetch_all(&self.pool)
+        .await
+        .context("failed to get uploads by name")
+    }
+
+    pub async fn get_upload_image(&self, id: &str) -> Result<Option<Vec<u8>>> {
+        sqlx::query_scalar(
+            "SELECT image FROM uploads
+                WHERE id = $1",
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await
+        .context("failed to get upload image")
+    }
+
+    /// Store an uploaded image. If the same image was uploaded before, the
+    /// existing upload is renamed to `name`, so it's found under the new name
+    pub async fn insert_upload(&self, id: &str, name: &str, image: &[u8]) -> Result<Upload> {
+        sqlx::query(
+            "INSERT INTO uploads (id, name, image, uploaded_at)
+                VALUES ($1, $2, $3, $4)
+                ON CONFLICT (id) DO UPDATE
+                SET name = $2",
+        )
+        .bind(id)
+        .bind(canonicalize_name(name))
+        .bind(image)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await
+        .context("failed to insert upload")?;
+        self.get_upload(id)
+            .await?
+            .context("upload missing after insert")
+    }
+
     pub async fn get_metadata(&self) -> Result<Option<Metadata>> {
         sqlx::query_as::<_, Metadata>(
             "SELECT * FROM metadata
@@ -45,12 +45,46 @@ impl SfImageUris {
     }
 }
 
+/// Rules text of a card or card face, used for text-only proxies
+#[derive(Deserialize, Default, Clone)]
+pub struct SfOracle {
+    pub mana_cost: Option<String>,
+    pub type_line: Option<String>,
+    pub oracle_text: Option<String>,
+    pub power: Option<String>,
+    pub toughness: Option<String>,
+}
+
+impl SfOracle {
+    /// Combine the faces of a card printed on a single side (e.g. split and
+    /// adventure cards)
+    fn join(faces: &[SfCardFace]) -> Self {
+        let join = |field: fn(&SfOracle) -> &Option<String>, separator: &str| {
+            let parts = faces
+                .iter()
+                .filter_map(|face| field(&face.oracle).as_deref())
+                .filter(|x| !x.is_empty())
+                .collect::<Vec<_>>();
+            (!parts.is_empty()).then(|| parts.join(separator))
+        };
+        SfOracle {
+            mana_cost: join(|x| &x.mana_cost, " // "),
+            type_line: join(|x| &x.type_line, " // "),
+            oracle_text: join(|x| &x.oracle_text, "\n//\n"),
+            power: join(|x| &x.power, " // "),
+            toughness: join(|x| &x.toughness, " // "),
+        }
+    }
+}
+
 #[derive(Deserialize)]
 pub struct SfCardFace {
     pub name: String,
     pub printed_name: Option<String>,
     pub flavor_name: Option<String>,
     pub image_uris: Option<SfImageUris>,
+    #[serde(flatten)]
+    pub oracle: SfOracle,
 }
 
 #[derive(Deserialize)]
@@ -61,6 +95,8 @@ pub struct SfCard {
     pub flavor_name: Option<String>,
     pub image_uris: Option<SfImageUris>,
     pub card_faces: Option<Vec<SfCardFace>>,
+    #[serde(flatten)]
+    pub oracle: SfOracle,
     pub card_back_id: Option<String>,
     pub set: String,
     pub set_name: String,
@@ -160,6 +196,8 @@ impl Downloader {
             let mut image_front_png = None;
             let mut image_back_jpg = None;
             let mut image_back_png = None;
+            let mut oracle_front = sf_card.oracle.clone();
+            let mut oracle_back = SfOracle::default();
             // Get card front image
             if let Some(imgs) = &sf_card.image_uris {
                 image_front_jpg = Some(imgs.jpg_clean()?);
@@ -167,9 +205,20 @@ impl Downloader {
             }
             // Handle card faces
             if let Some(faces) = &sf_card.card_faces {
+                // Faces with their own images are printed on separate sides
+                if faces.iter().any(|face| face.image_uris.is_some()) {
+                    if let Some(front) = faces.first() {
+                        oracle_front = front.oracle.clone();
+                    }
+                    if let Some(back) = faces.get(1) {
+                        oracle_back = back.oracle.clone();
+                    }
+                } else {
+                    oracle_front = SfOracle::join(faces);
+                }
                 let mut flavor_name_front = None;
                 let mut flavor_name_back = None;
-                if let Some(front) = faces.get(0) {
+                if let Some(front) = faces.first() {
                     if let Some(imgs) = &front.image_uris {
                         image_front_jpg = Some(imgs.jpg_clean()?);
                         image_front_png = Some(imgs.png_clean()?);
@@ -229,6 +278,16 @@ impl Downloader {
                 image_front_png,
                 image_back_jpg,
                 image_back_png,
+                mana_cost_front: oracle_front.mana_cost,
+                mana_cost_back: oracle_back.mana_cost,
+                type_line_front: oracle_front.type_line,
+                type_line_back: oracle_back.type_line,
+                oracle_text_front: oracle_front.oracle_text,
+                oracle_text_back: oracle_back.oracle_text,
+                power_front: oracle_front.power,
+                power_back: oracle_back.power,
+                toughness_front: oracle_front.toughness,
+                toughness_back: oracle_back.toughness,
                 set: sf_card.set,
                 set_name: sf_card.set_name,
                 collector_number: sf_card.collector_number,
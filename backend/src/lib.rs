@@ -2,12 +2,14 @@ mod database;
 mod downloader;
 mod env;
 mod server;
+mod upload;
 mod util;
 
 pub use database::*;
 pub use downloader::*;
 pub use env::*;
 pub use server::*;
+pub use upload::*;
 pub use util::*;
 
 use anyhow::{Context, Result};
@@ -1,8 +1,14 @@
-use crate::{AppState, database::Card, split_normalize_name};
-use anyhow::Result;
+use crate::{
+    AppState, MAX_UPLOAD_SIZE, canonicalize_name,
+    database::{Card, Upload},
+    is_upload_id, normalize_image, split_normalize_name,
+};
+use anyhow::{Context, Result};
 use axum::{
     Json, Router,
-    extract::{Query, State},
+    body::Bytes,
+    extract::{DefaultBodyLimit, Path, Query, State},
+    http::header,
     response::IntoResponse,
     routing::{get, post},
 };
@@ -60,6 +66,21 @@ struct ApiCardImages {
     back_png: Option<String>,
 }
 
+#[derive(Serialize)]
+struct ApiCardFaceText {
+    mana_cost: Option<String>,
+    type_line: Option<String>,
+    oracle_text: Option<String>,
+    power: Option<String>,
+    toughness: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ApiCardText {
+    front: ApiCardFaceText,
+    back: Option<ApiCardFaceText>,
+}
+
 #[derive(Serialize)]
 struct ApiCard {
     pub id: String,
@@ -69,6 +90,7 @@ struct ApiCard {
     pub set_name: String,
     pub collector_number: String,
     pub images: ApiCardImages,
+    pub text: ApiCardText,
     pub preferred: bool,
 }
 
@@ -87,11 +109,64 @@ impl From<Card> for ApiCard {
                 front_png: value.image_front_png,
                 back_png: value.image_back_png,
             },
+            text: ApiCardText {
+                front: ApiCardFaceText {
+                    mana_cost: value.mana_cost_front,
+                    type_line: value.type_line_front,
+                    oracle_text: value.oracle_text_front,
+                    power: value.power_front,
+                    toughness: value.toughness_front,
+                },
+                back: if value.type_line_back.is_some() {
+                    Some(ApiCardFaceText {
+                        mana_cost: value.mana_cost_back,
+                        type_line: value.type_line_back,
+                        oracle_text: value.oracle_text_back,
+                        power: value.power_back,
+                        toughness: value.toughness_back,
+                    })
+                } else {
+                    None
+                },
+            },
             preferred: value.preferred,
         }
     }
 }
 
+impl From<Upload> for ApiCard {
+    fn from(value: Upload) -> Self {
+        let url = format!("/api/uploads/{}", value.id);
+        let text = ApiCardFaceText {
+            mana_cost: None,
+            type_line: None,
+            oracle_text: None,
+            power: None,
+            toughness: None,
+        };
+        ApiCard {
+            // Short hash to tell uploads of the same card apart
+            collector_number: value.id[..8].to_string(),
+            id: value.id,
+            name: value.name,
+            flavor_name: None,
+            set: "custom".to_string(),
+            set_name: "Custom".to_string(),
+            images: ApiCardImages {
+                front_jpg: url.clone(),
+                back_jpg: None,
+                front_png: url,
+                back_png: None,
+            },
+            text: ApiCardText {
+                front: text,
+                back: None,
+            },
+            preferred: false,
+        }
+    }
+}
+
 /// Check if the API is working
 pub async fn get_ping() -> impl MyResponse {
     Ok("pong!")
@@ -103,22 +178,39 @@ pub struct GetCardsRequest {
     ids: Option<String>,
 }
 
-/// Get cards by exact card name or by ids
+/// Get cards by exact card name or by ids, including uploaded images after the
+/// printings of each card
 pub async fn get_cards(
     State(state): State<AppState>,
     Query(params): Query<GetCardsRequest>,
 ) -> impl MyResponse {
-    let mut cards = match (params.name, params.ids) {
-        (Some(name), None) => state.database.get_cards_by_name(&name).await.server_err()?,
+    let (mut cards, uploads) = match (params.name, params.ids) {
+        (Some(name), None) => (
+            state.database.get_cards_by_name(&name).await.server_err()?,
+            state
+                .database
+                .get_uploads_by_name(&name)
+                .await
+                .server_err()?,
+        ),
         (None, Some(ids)) => {
             let mut cards = Vec::new();
+            let mut uploads = Vec::new();
             for id in ids.split(",") {
-                let Some(card) = state.database.get_card(id.trim()).await.server_err()? else {
-                    return res_err!(StatusCode::NOT_FOUND, "card {id} not found");
-                };
-                cards.push(card);
+                let id = id.trim();
+                if is_upload_id(id) {
+                    let Some(upload) = state.database.get_upload(id).await.server_err()? else {
+                        return res_err!(StatusCode::NOT_FOUND, "card {id} not found");
+                    };
+                    uploads.push(upload);
+                } else {
+                    let Some(card) = state.database.get_card(id).await.server_err()? else {
+                        return res_err!(StatusCode::NOT_FOUND, "card {id} not found");
+                    };
+                    cards.push(card);
+                }
             }
-            cards
+            (cards, uploads)
         }
         _ => {
             return res_err!(
@@ -128,7 +220,9 @@ pub async fn get_cards(
         }
     };
     cards.sort_by_key(|card| Reverse(card.released_at));
-    let cards = cards.into_iter().map(ApiCard::from).collect::<Vec<_>>();
+    let cards = (cards.into_iter().map(ApiCard::from))
+        .chain(uploads.into_iter().map(ApiCard::from))
+        .collect::<Vec<_>>();
     res_json!({ "cards": cards })
 }
 
@@ -339,6 +433,60 @@ pub async fn post_import(
     res_json!({ "results": output })
 }
 
+#[derive(Deserialize)]
+pub struct PostUploadRequest {
+    name: String,
+}
+
+/// Upload a custom card image, sent as the request body. The image is
+/// normalized to the aspect ratio of a card and stored by the hash of its
+/// content, so uploading the same image twice returns the same card
+pub async fn post_upload(
+    State(state): State<AppState>,
+    Query(params): Query<PostUploadRequest>,
+    body: Bytes,
+) -> impl MyResponse {
+    let name = canonicalize_name(&params.name);
+    if name.is_empty() {
+        return res_err!(StatusCode::BAD_REQUEST, "Unexpected empty card name");
+    }
+    // Decoding and resizing large images would block other requests
+    let image = tokio::task::spawn_blocking(move || normalize_image(&body))
+        .await
+        .context("failed to normalize image")
+        .server_err()?;
+    let image = match image {
+        Ok(image) => image,
+        Err(err) => return res_err!(StatusCode::BAD_REQUEST, "Invalid image: {err}"),
+    };
+    let upload = state
+        .database
+        .insert_upload(&image.hash, &name, &image.png)
+        .await
+        .server_err()?;
+    res_json!({ "card": ApiCard::from(upload) })
+}
+
+/// Get an uploaded image as png. Images never change, since they are
+/// identified by the hash of their content
+pub async fn get_upload_image(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> impl MyResponse {
+    let image = match is_upload_id(&id) {
+        true => state.database.get_upload_image(&id).await.server_err()?,
+        false => None,
+    };
+    let Some(image) = image else {
+        return res_err!(StatusCode::NOT_FOUND, "upload {id} not found");
+    };
+    let headers = [
+        (header::CONTENT_TYPE, "image/png"),
+        (header::CACHE_CONTROL, "public, max-age=31536000, immutable"),
+    ];
+    Ok((headers, image))
+}
+
 pub fn build_router(app_state: AppState, public_dir: &str) -> Router {
     let serve_dir = ServeDir::new(public_dir);
     Router::new()
@@ -346,6 +494,11 @@ pub fn build_router(app_state: AppState, public_dir: &str) -> Router {
         .route("/api/search", get(get_search))
         .route("/api/cards", get(get_cards))
         .route("/api/import", post(post_import))
+        .route(
+            "/api/uploads",
+            post(post_upload).layer(DefaultBodyLimit::max(MAX_UPLOAD_SIZE)),
+        )
+        .route("/api/uploads/:id", get(get_upload_image))
         .with_state(app_state)
         .fallback_service(serve_dir)
 }
@@ -0,0 +1,76 @@
+use anyhow::{Context, Result, bail};
+use image::{DynamicImage, ImageFormat, ImageReader, imageops::FilterType};
+use sha2::{Digest, Sha256};
+use std::io::Cursor;
+
+/// Largest accepted upload in bytes
+pub const MAX_UPLOAD_SIZE: usize = 20 * 1024 * 1024;
+// Accepted image dimensions in pixels. Smaller images print too blurry to use
+const MIN_DIMENSION: u32 = 200;
+const MAX_DIMENSION: u32 = 10_000;
+// Aspect ratio of a card, from its size in millimetres
+const CARD_WIDTH: u32 = 63;
+const CARD_HEIGHT: u32 = 88;
+// Normalized images are scaled down to this width, about 600 dpi
+const MAX_WIDTH: u32 = 1488;
+
+/// Uploaded image normalized to the aspect ratio of a card
+pub struct NormalizedImage {
+    /// Hex encoded sha-256 hash of the png, used as its id
+    pub hash: String,
+    pub png: Vec<u8>,
+}
+
+/// Validate an uploaded image and normalize it to a portrait card. Landscape
+/// images are turned clockwise (like Scryfall's images of battles), the longer
+/// side is cropped equally from both ends to the aspect ratio of a card, and
+/// large images are scaled down
+pub fn normalize_image(data: &[u8]) -> Result<NormalizedImage> {
+    let reader = || ImageReader::new(Cursor::new(data)).with_guessed_format();
+    match reader()?.format() {
+        Some(ImageFormat::Png | ImageFormat::Jpeg | ImageFormat::WebP) => {}
+        _ => bail!("unsupported image format, expected png, jpeg or webp"),
+    }
+    let (width, height) = reader()?.into_dimensions()?;
+    if width.min(height) < MIN_DIMENSION {
+        bail!("image is too small ({width}x{height}), expected at least {MIN_DIMENSION} pixels");
+    }
+    if width.max(height) > MAX_DIMENSION {
+        bail!("image is too large ({width}x{height}), expected at most {MAX_DIMENSION} pixels");
+    }
+    let mut image = reader()?.decode()?;
+
+    if image.width() > image.height() {
+        image = image.rotate90();
+    }
+    let (width, height) = (image.width(), image.height());
+    if width * CARD_HEIGHT > height * CARD_WIDTH {
+        let cropped = height * CARD_WIDTH / CARD_HEIGHT;
+        image = image.crop_imm((width - cropped) / 2, 0, cropped, height);
+    } else {
+        let cropped = width * CARD_HEIGHT / CARD_WIDTH;
+        image = image.crop_imm(0, (height - cropped) / 2, width, cropped);
+    }
+    if image.width() > MAX_WIDTH {
+        let height = MAX_WIDTH * CARD_HEIGHT / CARD_WIDTH;
+        image = image.resize_exact(MAX_WIDTH, height, FilterType::Lanczos3);
+    }
+
+    // Transparency (e.g. rounded corners) is kept
+    let image = if image.color().has_alpha() {
+        DynamicImage::from(image.into_rgba8())
+    } else {
+        DynamicImage::from(image.into_rgb8())
+    };
+    let mut png = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .context("failed to encode png")?;
+    let hash = format!("{:x}", Sha256::digest(&png));
+    Ok(NormalizedImage { hash, png })
+}
+
+/// Check if `id` is the hash of an uploaded image
+pub fn is_upload_id(id: &str) -> bool {
+    id.len() == 64 && id.bytes().all(|x| matches!(x, b'0'..=b'9' | b'a'..=b'f'))
+}
@@ -0,0 +1,129 @@
+use std::io::Cursor;
+
+use axum::{
+    Router,
+    body::{Body, to_bytes},
+    http::{Request, StatusCode},
+};
+use backend::{AppState, Database, Downloader, MAX_UPLOAD_SIZE, build_router};
+use image::{DynamicImage, ImageFormat, Rgb, RgbImage};
+use serde_json::Value;
+use tokio_util::sync::CancellationToken;
+use tower::ServiceExt;
+
+/// Router with an empty database in a temporary file named after the test
+async fn router(test: &str) -> (Router, AppState) {
+    let file = std::env::temp_dir().join(format!("backend-{}-{test}.db", std::process::id()));
+    let _ = std::fs::remove_file(&file);
+    let database = Database::open(file.to_str().unwrap()).await.unwrap();
+    database.init().await.unwrap();
+    let state = AppState::new(
+        Downloader::new().unwrap(),
+        database,
+        CancellationToken::new(),
+    );
+    (build_router(state.clone(), "public"), state)
+}
+
+fn encode(width: u32, height: u32, format: ImageFormat) -> Vec<u8> {
+    let image = RgbImage::from_fn(width, height, |x, y| Rgb([x as u8, y as u8, 0x80]));
+    let mut data = Vec::new();
+    DynamicImage::ImageRgb8(image)
+        .write_to(&mut Cursor::new(&mut data), format)
+        .unwrap();
+    data
+}
+
+async fn upload(router: &Router, name: &str, data: Vec<u8>) -> (StatusCode, Value) {
+    let request = Request::post(format!("/api/uploads?name={name}"))
+        .body(Body::from(data))
+        .unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+async fn uploaded_image(router: &Router, id: &str) -> DynamicImage {
+    let request = Request::get(format!("/api/uploads/{id}"))
+        .body(Body::empty())
+        .unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    image::load_from_memory_with_format(&body, ImageFormat::Png).unwrap()
+}
+
+#[tokio::test]
+async fn invalid_uploads_are_rejected() {
+    let (router, _) = router("invalid").await;
+
+    let (status, _) = upload(&router, "Card", vec![0; MAX_UPLOAD_SIZE + 1]).await;
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+
+    let (status, error) = upload(&router, "Card", b"not an image".to_vec()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(error.as_str().unwrap().contains("unsupported image format"));
+
+    // Gifs are recognized, but not accepted
+    let (status, error) = upload(&router, "Card", b"GIF89a\x01\x00\x01\x00".to_vec()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(error.as_str().unwrap().contains("unsupported image format"));
+
+    // Too small to print sharply
+    let (status, error) = upload(&router, "Card", encode(100, 140, ImageFormat::Png)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(error.as_str().unwrap().contains("too small"));
+
+    let (status, error) = upload(&router, "Card", encode(10_001, 200, ImageFormat::Png)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(error.as_str().unwrap().contains("too large"));
+
+    let (status, _) = upload(&router, "%20", encode(630, 880, ImageFormat::Png)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn uploads_are_normalized_to_portrait_cards() {
+    let (router, _) = router("normalized").await;
+
+    // Landscape images are turned sideways and cropped to the card's aspect
+    let (status, body) = upload(&router, "Battle", encode(1000, 500, ImageFormat::Jpeg)).await;
+    assert_eq!(status, StatusCode::OK);
+    let card = &body["card"];
+    assert_eq!(card["set"], "custom");
+    let image = uploaded_image(&router, card["id"].as_str().unwrap()).await;
+    assert_eq!((image.width(), image.height()), (500, 698));
+
+    // Large images are scaled down
+    let (status, body) = upload(&router, "Large", encode(1600, 2400, ImageFormat::Png)).await;
+    assert_eq!(status, StatusCode::OK);
+    let image = uploaded_image(&router, body["card"]["id"].as_str().unwrap()).await;
+    assert_eq!((image.width(), image.height()), (1488, 2078));
+}
+
+#[tokio::test]
+async fn duplicate_uploads_are_stored_once_under_the_latest_name() {
+    let (router, state) = router("duplicate").await;
+    let data = encode(630, 880, ImageFormat::Png);
+
+    let (status, first) = upload(&router, "First", data.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(first["card"]["name"], "First");
+    let (status, second) = upload(&router, "Second", data).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(second["card"]["id"], first["card"]["id"]);
+    assert_eq!(second["card"]["name"], "Second");
+
+    let database = &state.database;
+    assert!(
+        database
+            .get_uploads_by_name("First")
+            .await
+            .unwrap()
+            .is_empty()
+    );
+    let uploads = database.get_uploads_by_name("Second").await.unwrap();
+    assert_eq!(uploads.len(), 1);
+    assert_eq!(uploads[0].id, first["card"]["id"].as_str().unwrap());
+}
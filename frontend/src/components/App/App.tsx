@@ -10,6 +10,7 @@ import { LocalStorage } from "../LocalStorage/LocalStorage";
 import { Print } from "../Print/Print";
 import * as styles from "./App.css";
 import { Split } from "../Split/Split";
+import { Upload } from "../Upload/Upload";
 
 export function App() {
   return (
@@ -28,6 +29,7 @@ export function App() {
       <div className={styles.footer}>
         <div className={styles.row}>
           <Import />
+          <Upload />
           <Export />
           <Split />
           <Clear />
@@ -52,7 +52,7 @@ export function Print() {
     };
   }, []);
 
-  const handleClick = async () => {
+  const handleClick = async (textOnly: boolean) => {
     const { data, status } = await fetchCards({
       ids: print.cards.map((x) => x.id),
     });
@@ -68,6 +68,23 @@ export function Print() {
         setMessage(`Error fetching card data (${card.id})`);
         return;
       }
+      if (textOnly) {
+        const face = card.face === "front" ? info.text.front : info.text.back;
+        if (!face) {
+          setMessage(
+            `Error fetching card data (${card.id} missing back text)`,
+          );
+          return;
+        }
+        // Names of double faced cards are "front // back"
+        const names = info.name.split(" // ");
+        let name = info.name;
+        if (info.text.back) {
+          name = card.face === "front" ? names[0] : names[names.length - 1];
+        }
+        reqCards.push({ count: card.quantity, text: { name, ...face } });
+        continue;
+      }
       let url: string;
       if (card.face === "front") {
         url = info.images.front_png;
@@ -80,7 +97,7 @@ export function Print() {
         }
         url = info.images.back_png;
       }
-      reqCards.push({ count: card.quantity, url });
+      reqCards.push({ count: card.quantity, url, name: info.name });
     }
     const request: WorkerRequest = {
       type: "print",
@@ -95,7 +112,13 @@ export function Print() {
     <div className={container}>
       <p className={statusMessage}>{message}</p>
       <Button
-        onClick={handleClick}
+        onClick={() => handleClick(true)}
+        disabled={print.cards.length === 0 || disabled}
+      >
+        Print text
+      </Button>
+      <Button
+        onClick={() => handleClick(false)}
         disabled={print.cards.length === 0 || disabled}
       >
         Print
@@ -1,11 +1,20 @@
-import { PrintJob } from "mtg-print";
+import { PrintJob, TextCard } from "mtg-print";
 import { chunk } from "../../util";
 import JSZip from "jszip";
 
+export type WorkerRequestText = {
+  name: string;
+  mana_cost: string | null;
+  type_line: string | null;
+  oracle_text: string | null;
+  power: string | null;
+  toughness: string | null;
+};
+
+// Cards are printed from their image, or as text-only proxies
 export type WorkerRequestCard = {
   count: number;
-  url: string;
-};
+} & ({ url: string; name: string } | { text: WorkerRequestText });
 
 export type WorkerRequest = {
   type: "print";
@@ -37,17 +46,20 @@ function progress(message: string) {
 
 async function downloadImages(
   cards: WorkerRequestCard[],
-): Promise<Uint8Array[]> {
-  progress(`Downloading images (0 / ${cards.length})`);
+): Promise<(Uint8Array | null)[]> {
+  const total = cards.filter((card) => "url" in card).length;
+  progress(`Downloading images (0 / ${total})`);
   let count = 0;
   const cardPngs = [];
   const jobs = cards.map((card) =>
-    fetch(card.url)
-      .then((result) => result.arrayBuffer())
-      .then((buffer) => {
-        progress(`Downloading images (${++count} / ${cards.length})`);
-        return new Uint8Array(buffer);
-      }),
+    "url" in card
+      ? fetch(card.url)
+          .then((result) => result.arrayBuffer())
+          .then((buffer) => {
+            progress(`Downloading images (${++count} / ${total})`);
+            return new Uint8Array(buffer);
+          })
+      : Promise.resolve(null),
   );
   // Download in groups of 8
   const chunks = chunk(jobs, 8);
@@ -100,9 +112,32 @@ self.addEventListener("message", async (event: MessageEvent<WorkerRequest>) => {
   const blobs = [];
   for (let i = 0; i < partitions.length; i++) {
     const job = new PrintJob();
+    // Titled after the file it is downloaded as
+    job.set_title(
+      partitions.length === 1 ? "MTG Proxy" : `MTG Proxy ${i + 1}`,
+    );
     try {
+      // Index of the next card added to the job
+      let added = 0;
       for (const { count, id } of partitions[i]) {
-        job.add_card(count, cardPngs[id]);
+        const card = cards[id];
+        const png = cardPngs[id];
+        if ("text" in card) {
+          const text = new TextCard(card.text.name);
+          text.mana_cost = card.text.mana_cost ?? undefined;
+          text.type_line = card.text.type_line ?? undefined;
+          text.oracle_text = card.text.oracle_text ?? undefined;
+          text.power = card.text.power ?? undefined;
+          text.toughness = card.text.toughness ?? undefined;
+          job.add_text_card(count, text);
+          text.free();
+          added++;
+        } else if (png) {
+          job.add_card(count, png);
+          // Names are listed in the pdf outline
+          job.set_card_name(added, card.name);
+          added++;
+        }
       }
       job.add_callback((message: string) => {
         progress(message);
@@ -113,7 +148,7 @@ self.addEventListener("message", async (event: MessageEvent<WorkerRequest>) => {
     } catch (error) {
       self.postMessage({
         type: "failed",
-        message: `Error: ${error}`,
+        message: `Error: ${error instanceof Error ? error.message : error}`,
       } as WorkerResponse);
       return;
     } finally {
@@ -0,0 +1,16 @@
+import { style } from "@vanilla-extract/css";
+
+export const form = style({
+  display: "flex",
+  flexDirection: "column",
+  gap: "8px",
+});
+
+export const error = style({
+  margin: "0px",
+});
+
+export const buttonRow = style({
+  display: "flex",
+  gap: "8px",
+});
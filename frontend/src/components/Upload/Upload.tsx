@@ -0,0 +1,100 @@
+import { useState } from "react";
+import { useAppDispatch } from "../../state";
+import { useUploadMutation } from "../../state/api";
+import { printAction } from "../../state/print";
+import { Button } from "../common/Button/Button";
+import { Dialog } from "../common/Dialog/Dialog";
+import { Input } from "../common/Input/Input";
+import * as styles from "./Upload.css";
+
+export function Upload() {
+  const dispatch = useAppDispatch();
+  const [dialogOpen, setDialogOpen] = useState(false);
+  const [file, setFile] = useState<File | null>(null);
+  const [name, setName] = useState("");
+  const [error, setError] = useState("");
+
+  const [upload, { isLoading }] = useUploadMutation();
+
+  const handleClose = () => {
+    setDialogOpen(false);
+    setFile(null);
+    setName("");
+    setError("");
+  };
+
+  const handleUpload = async () => {
+    if (!file || name.trim().length === 0) {
+      setError("Choose an image and enter a card name.");
+      return;
+    }
+    setError("");
+    const result = await upload({ name: name.trim(), file });
+    if ("error" in result) {
+      const error = result.error;
+      setError(
+        "data" in error && typeof error.data === "string"
+          ? error.data
+          : "Unable to upload image.",
+      );
+      return;
+    }
+    const card = result.data.card;
+    dispatch(
+      printAction.add({
+        id: card.id,
+        name: card.name,
+        quantity: 1,
+        face: "front",
+      }),
+    );
+    handleClose();
+  };
+
+  return (
+    <>
+      <Dialog open={dialogOpen} onClose={handleClose}>
+        <form
+          className={styles.form}
+          onSubmit={(e) => {
+            e.preventDefault();
+            handleUpload();
+          }}
+        >
+          <input
+            type="file"
+            accept="image/png,image/jpeg,image/webp"
+            onChange={(e) => {
+              const file = e.target.files?.[0] ?? null;
+              setFile(file);
+              setError("");
+              // Custom cards are named after their file by default
+              if (file && name.length === 0) {
+                setName(file.name.replace(/\.[^.]*$/, ""));
+              }
+            }}
+          />
+          <Input
+            type="text"
+            placeholder="Card name"
+            value={name}
+            onChange={(e) => {
+              setName(e.target.value);
+              setError("");
+            }}
+          />
+          {error && <p className={styles.error}>{error}</p>}
+          <div className={styles.buttonRow}>
+            <Button type="submit" disabled={isLoading}>
+              {isLoading ? "Uploading..." : "Upload"}
+            </Button>
+            <Button variant="secondary" onClick={handleClose}>
+              Cancel
+            </Button>
+          </div>
+        </form>
+      </Dialog>
+      <Button onClick={() => setDialogOpen(true)}>Upload</Button>
+    </>
+  );
+}
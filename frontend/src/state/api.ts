@@ -7,6 +7,19 @@ type ApiCardImages = {
   back_png: string | null;
 };
 
+export type ApiCardFaceText = {
+  mana_cost: string | null;
+  type_line: string | null;
+  oracle_text: string | null;
+  power: string | null;
+  toughness: string | null;
+};
+
+type ApiCardText = {
+  front: ApiCardFaceText;
+  back: ApiCardFaceText | null;
+};
+
 export type ApiCard = {
   id: string;
   name: string;
@@ -15,6 +28,7 @@ export type ApiCard = {
   set_name: string;
   collector_number: string;
   images: ApiCardImages;
+  text: ApiCardText;
   preferred: boolean;
 };
 
@@ -65,6 +79,15 @@ export const scryfallApi = createApi({
         body,
       }),
     }),
+    // Custom card images are sent as is, and normalized by the server
+    upload: builder.mutation<{ card: ApiCard }, { name: string; file: File }>({
+      query: ({ name, file }) => ({
+        method: "POST",
+        url: `/api/uploads?name=${encodeURIComponent(name)}`,
+        headers: { "Content-Type": file.type },
+        body: file,
+      }),
+    }),
   }),
 });
 
@@ -75,6 +98,7 @@ export const {
   useLazyCardsQuery,
   useImportQuery,
   useLazyImportQuery,
+  useUploadMutation,
 } = scryfallApi;
 
 export function isDfc(card: ApiCard): card is ApiCard & {
@@ -0,0 +1,69 @@
+//! Compare single threaded and parallel pdf generation
+//!
+//! cargo run --release --features parallel --example bench -- [IMAGE]...
+//!
+//! Card images are generated if no image files are given
+
+use anyhow::Result;
+use image::{DynamicImage, ImageFormat, Rgb, RgbImage};
+use mtg_print::print::PrintJob;
+use rayon::ThreadPoolBuilder;
+use std::{env, fs, io::Cursor, time::Instant};
+
+// Number of distinct cards and copies of each when images are generated
+const CARDS: u32 = 36;
+const COPIES: u32 = 2;
+
+/// Card image with noise, so it compresses about as well as a real card
+fn generate_card(seed: u32) -> Result<Vec<u8>> {
+    let mut state = seed.wrapping_mul(2654435761).wrapping_add(1);
+    let image = RgbImage::from_fn(745, 1040, |x, y| {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        let noise = (state % 32) as u8;
+        Rgb([
+            (x / 3) as u8 ^ noise,
+            (y / 4) as u8,
+            (seed * 40) as u8 ^ noise,
+        ])
+    });
+    let mut buffer = Vec::new();
+    DynamicImage::ImageRgb8(image).write_to(&mut Cursor::new(&mut buffer), ImageFormat::Png)?;
+    Ok(buffer)
+}
+
+fn main() -> Result<()> {
+    let paths = env::args().skip(1).collect::<Vec<_>>();
+    let cards = if paths.is_empty() {
+        (0..CARDS)
+            .map(|i| Ok((COPIES, generate_card(i)?)))
+            .collect::<Result<Vec<_>>>()?
+    } else {
+        paths
+            .iter()
+            .map(|path| Ok((1, fs::read(path)?)))
+            .collect::<Result<Vec<_>>>()?
+    };
+    let new_job = || {
+        let mut job = PrintJob::new();
+        for (count, data) in &cards {
+            job.add_card(*count, Box::from(data.as_slice()));
+        }
+        job
+    };
+
+    let threads = rayon::current_num_threads();
+    for threads in [1, threads] {
+        let pool = ThreadPoolBuilder::new().num_threads(threads).build()?;
+        let start = Instant::now();
+        // Jobs aren't `Send`, so each is created on the pool
+        let pdf = pool.install(|| new_job().print())?;
+        println!(
+            "{threads:>3} threads: {:>8.2?} ({} bytes)",
+            start.elapsed(),
+            pdf.len()
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,148 @@
+use anyhow::{Result, bail};
+use image::{DynamicImage, Rgba, RgbaImage, imageops};
+use wasm_bindgen::prelude::wasm_bindgen;
+
+// Blur radius of the unsharp mask used for sharpening, in source pixels
+const SHARPEN_SIGMA: f32 = 1.0;
+
+/// Color and sharpness adjustments applied to card images before they are
+/// resized. The default values leave images unchanged
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Adjustments {
+    /// Amount added to each channel, from -1 to 1
+    pub brightness: f32,
+    /// Contrast multiplier around middle gray
+    pub contrast: f32,
+    /// Saturation multiplier, where 0 is grayscale
+    pub saturation: f32,
+    /// Gamma correction, where values above 1 lighten midtones
+    pub gamma: f32,
+    /// Strength of the unsharp mask, where 0 is no sharpening
+    pub sharpen: f32,
+}
+
+impl Default for Adjustments {
+    fn default() -> Self {
+        Adjustments {
+            brightness: 0.0,
+            contrast: 1.0,
+            saturation: 1.0,
+            gamma: 1.0,
+            sharpen: 0.0,
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl Adjustments {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Adjustments::default()
+    }
+
+    /// Preset that compensates for home inkjet printers, which print scans
+    /// dark and desaturated
+    #[wasm_bindgen]
+    pub fn print_friendly() -> Self {
+        Adjustments {
+            brightness: 0.03,
+            contrast: 1.08,
+            saturation: 1.15,
+            gamma: 1.2,
+            sharpen: 0.5,
+        }
+    }
+}
+
+impl Adjustments {
+    pub fn is_identity(&self) -> bool {
+        *self == Adjustments::default()
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        let Adjustments {
+            brightness,
+            contrast,
+            saturation,
+            gamma,
+            sharpen,
+        } = *self;
+        if !(-1.0..=1.0).contains(&brightness) {
+            bail!("invalid brightness {brightness}");
+        }
+        if !(contrast.is_finite() && contrast >= 0.0) {
+            bail!("invalid contrast {contrast}");
+        }
+        if !(saturation.is_finite() && saturation >= 0.0) {
+            bail!("invalid saturation {saturation}");
+        }
+        if !(gamma.is_finite() && gamma > 0.0) {
+            bail!("invalid gamma {gamma}");
+        }
+        if !(sharpen.is_finite() && sharpen >= 0.0) {
+            bail!("invalid sharpen amount {sharpen}");
+        }
+        Ok(())
+    }
+
+    /// Apply the adjustments to an image, keeping its alpha channel
+    pub fn apply(&self, image: DynamicImage) -> DynamicImage {
+        if self.is_identity() {
+            return image;
+        }
+        let has_alpha = image.color().has_alpha();
+        let mut rgba = image.into_rgba8();
+
+        // Brightness, contrast and gamma only depend on the channel value
+        let tone = self.tone_curve();
+        for Rgba([r, g, b, _]) in rgba.pixels_mut() {
+            let [r2, g2, b2] = [*r, *g, *b].map(|c| tone[c as usize] as f32);
+            let luma = 0.299 * r2 + 0.587 * g2 + 0.114 * b2;
+            let saturate = |c: f32| {
+                (luma + (c - luma) * self.saturation)
+                    .round()
+                    .clamp(0.0, 255.0)
+            };
+            *r = saturate(r2) as u8;
+            *g = saturate(g2) as u8;
+            *b = saturate(b2) as u8;
+        }
+
+        if self.sharpen > 0.0 {
+            rgba = self.sharpen(&rgba);
+        }
+        if has_alpha {
+            DynamicImage::from(rgba)
+        } else {
+            DynamicImage::from(DynamicImage::from(rgba).into_rgb8())
+        }
+    }
+
+    /// Lookup table of adjusted values for each channel value
+    fn tone_curve(&self) -> [u8; 256] {
+        let mut curve = [0; 256];
+        for (value, output) in curve.iter_mut().enumerate() {
+            let v = value as f32 / 255.0 + self.brightness;
+            let v = ((v - 0.5) * self.contrast + 0.5).clamp(0.0, 1.0);
+            let v = v.powf(1.0 / self.gamma);
+            *output = (v * 255.0).round() as u8;
+        }
+        curve
+    }
+
+    /// Unsharp mask, which adds the difference between the image and a
+    /// blurred copy of it
+    fn sharpen(&self, image: &RgbaImage) -> RgbaImage {
+        let blurred = imageops::blur(image, SHARPEN_SIGMA);
+        let mut output = image.clone();
+        for (pixel, blurred) in output.pixels_mut().zip(blurred.pixels()) {
+            for c in 0..3 {
+                let value = pixel[c] as f32;
+                let detail = value - blurred[c] as f32;
+                pixel[c] = (value + detail * self.sharpen).round().clamp(0.0, 255.0) as u8;
+            }
+        }
+        output
+    }
+}
@@ -0,0 +1,116 @@
+use std::sync::Arc;
+
+use anyhow::{Result, anyhow, bail};
+use lopdf::{Dictionary, Object, dictionary};
+use moxcms::{ColorProfile, DataColorSpace, Layout, Transform8BitExecutor, TransformOptions};
+use wasm_bindgen::prelude::wasm_bindgen;
+
+/// Color space of card images in the output
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorMode {
+    /// Uncalibrated RGB, converted by the printer driver
+    DeviceRgb,
+    /// sRGB, with an embedded sRGB profile as the output intent
+    Srgb,
+    /// CMYK converted with a user supplied ICC profile, which is embedded as
+    /// the output intent
+    Cmyk,
+}
+
+/// Conversion of card images to the output color space
+#[derive(Clone)]
+pub(crate) enum ColorOutput {
+    DeviceRgb,
+    Srgb,
+    Cmyk {
+        profile: Arc<[u8]>,
+        transform: Arc<Transform8BitExecutor>,
+    },
+}
+
+impl ColorOutput {
+    pub fn new(mode: ColorMode, profile: Option<Box<[u8]>>) -> Result<Self> {
+        match (mode, profile) {
+            (ColorMode::DeviceRgb, _) => Ok(ColorOutput::DeviceRgb),
+            (ColorMode::Srgb, _) => Ok(ColorOutput::Srgb),
+            (ColorMode::Cmyk, None) => bail!("cmyk output requires an icc profile"),
+            (ColorMode::Cmyk, Some(profile)) => {
+                let cmyk = ColorProfile::new_from_slice(&profile)
+                    .map_err(|err| anyhow!("invalid icc profile: {err}"))?;
+                if cmyk.color_space != DataColorSpace::Cmyk {
+                    bail!("icc profile is not a cmyk profile");
+                }
+                // Cmyk pixels use the same layout as rgba
+                let transform = ColorProfile::new_srgb()
+                    .create_transform_8bit(
+                        Layout::Rgb,
+                        &cmyk,
+                        Layout::Rgba,
+                        TransformOptions::default(),
+                    )
+                    .map_err(|err| anyhow!("unsupported icc profile: {err}"))?;
+                Ok(ColorOutput::Cmyk {
+                    profile: Arc::from(profile),
+                    transform,
+                })
+            }
+        }
+    }
+
+    /// Whether rgb images can be embedded without conversion
+    pub fn is_rgb(&self) -> bool {
+        !matches!(self, ColorOutput::Cmyk { .. })
+    }
+
+    /// Convert rgb pixels to the output color space, returning the pixels and
+    /// the name of their device color space
+    pub fn convert(&self, rgb: Vec<u8>) -> Result<(Vec<u8>, &'static str)> {
+        match self {
+            ColorOutput::DeviceRgb | ColorOutput::Srgb => Ok((rgb, "DeviceRGB")),
+            ColorOutput::Cmyk { transform, .. } => {
+                let mut cmyk = vec![0; rgb.len() / 3 * 4];
+                transform
+                    .transform(&rgb, &mut cmyk)
+                    .map_err(|err| anyhow!("could not convert image to cmyk: {err}"))?;
+                Ok((cmyk, "DeviceCMYK"))
+            }
+        }
+    }
+
+    /// ICC profile embedded as the output intent, and the dictionary of its
+    /// stream
+    pub fn icc_profile(&self) -> Result<Option<(Vec<u8>, Dictionary)>> {
+        match self {
+            ColorOutput::DeviceRgb => Ok(None),
+            ColorOutput::Srgb => {
+                let profile = ColorProfile::new_srgb()
+                    .encode()
+                    .map_err(|err| anyhow!("could not encode srgb profile: {err}"))?;
+                let dict = dictionary! { "N" => 3, "Alternate" => "DeviceRGB" };
+                Ok(Some((profile, dict)))
+            }
+            ColorOutput::Cmyk { profile, .. } => {
+                let dict = dictionary! { "N" => 4, "Alternate" => "DeviceCMYK" };
+                Ok(Some((profile.to_vec(), dict)))
+            }
+        }
+    }
+
+    /// Output intent dictionary, which tells the printer how colors in the
+    /// document were prepared
+    pub fn output_intent(&self, profile: Object) -> Dictionary {
+        let (identifier, info) = match self {
+            ColorOutput::Cmyk { .. } => ("Custom", "CMYK output profile"),
+            _ => ("sRGB IEC61966-2.1", "sRGB IEC61966-2.1"),
+        };
+        dictionary! {
+            "Type" => "OutputIntent",
+            "S" => "GTS_PDFX",
+            "OutputConditionIdentifier" => Object::string_literal(identifier),
+            "RegistryName" => Object::string_literal("http://www.color.org"),
+            "Info" => Object::string_literal(info),
+            "DestOutputProfile" => profile,
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use std::fmt::{self, Display};
+
+use js_sys::Reflect;
+use wasm_bindgen::{JsValue, prelude::wasm_bindgen};
+
+/// Kind of a `PrintError`, set as the `kind` property of errors thrown to
+/// javascript
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    Decode,
+    UnsupportedFormat,
+    OversizedImage,
+    Layout,
+    UnsupportedOutput,
+    Write,
+    Cancelled,
+}
+
+/// Error from a print job. Card indices are zero based, in the order cards
+/// were added, and `None` refers to the shared card back
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PrintError {
+    /// Card image is corrupt or truncated
+    Decode {
+        card: Option<usize>,
+        message: String,
+    },
+    /// Card image is not in a supported image format
+    UnsupportedFormat { card: Option<usize> },
+    /// Card image is too large to be processed
+    OversizedImage {
+        card: Option<usize>,
+        width: u32,
+        height: u32,
+    },
+    /// Cards don't fit on the page
+    Layout(String),
+    /// Job uses a feature the output format can't represent
+    UnsupportedOutput(String),
+    /// Pdf or other output could not be written
+    Write(String),
+    /// Job was cancelled with its `CancelToken`
+    Cancelled,
+}
+
+impl PrintError {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            PrintError::Decode { .. } => ErrorKind::Decode,
+            PrintError::UnsupportedFormat { .. } => ErrorKind::UnsupportedFormat,
+            PrintError::OversizedImage { .. } => ErrorKind::OversizedImage,
+            PrintError::Layout(_) => ErrorKind::Layout,
+            PrintError::UnsupportedOutput(_) => ErrorKind::UnsupportedOutput,
+            PrintError::Write(_) => ErrorKind::Write,
+            PrintError::Cancelled => ErrorKind::Cancelled,
+        }
+    }
+
+    /// Whether the error is caused by a bad card image, rather than the job
+    /// as a whole
+    pub fn is_card_error(&self) -> bool {
+        matches!(
+            self,
+            PrintError::Decode { .. }
+                | PrintError::UnsupportedFormat { .. }
+                | PrintError::OversizedImage { .. }
+        )
+    }
+
+    /// Index of the card that caused the error
+    pub fn card(&self) -> Option<usize> {
+        match self {
+            PrintError::Decode { card, .. }
+            | PrintError::UnsupportedFormat { card }
+            | PrintError::OversizedImage { card, .. } => *card,
+            _ => None,
+        }
+    }
+}
+
+/// Human readable name of a card for error messages
+struct CardName(Option<usize>);
+
+impl Display for CardName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(idx) => write!(f, "card {}", idx + 1),
+            None => write!(f, "card back"),
+        }
+    }
+}
+
+impl Display for PrintError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PrintError::Decode { card, message } => {
+                write!(
+                    f,
+                    "could not decode image of {}: {message}",
+                    CardName(*card)
+                )
+            }
+            PrintError::UnsupportedFormat { card } => {
+                write!(
+                    f,
+                    "image of {} is not in a supported format",
+                    CardName(*card)
+                )
+            }
+            PrintError::OversizedImage {
+                card,
+                width,
+                height,
+            } => write!(
+                f,
+                "image of {} is too large ({width}x{height})",
+                CardName(*card)
+            ),
+            PrintError::Layout(message) => write!(f, "{message}"),
+            PrintError::UnsupportedOutput(message) => write!(f, "{message}"),
+            PrintError::Write(message) => write!(f, "could not write output: {message}"),
+            PrintError::Cancelled => write!(f, "print job was cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for PrintError {}
+
+impl From<anyhow::Error> for PrintError {
+    fn from(err: anyhow::Error) -> Self {
+        PrintError::Write(format!("{err:#}"))
+    }
+}
+
+impl From<lopdf::Error> for PrintError {
+    fn from(err: lopdf::Error) -> Self {
+        PrintError::Write(err.to_string())
+    }
+}
+
+impl From<PrintError> for JsValue {
+    fn from(err: PrintError) -> Self {
+        let error = js_sys::Error::new(&err.to_string());
+        let card = match err.card() {
+            Some(card) => JsValue::from(card as u32),
+            None => JsValue::null(),
+        };
+        let _ = Reflect::set(&error, &"kind".into(), &err.kind().into());
+        let _ = Reflect::set(&error, &"card".into(), &card);
+        error.into()
+    }
+}
@@ -0,0 +1,456 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write as _,
+    io::Write,
+};
+
+use anyhow::{Result, anyhow};
+use lopdf::{Object, ObjectId, Stream, StringFormat, content::Operation, dictionary};
+use ttf_parser::{Face, GlyphId, OutlineBuilder};
+
+use crate::pdf::PdfWriter;
+
+// DejaVu Serif Condensed, see fonts/LICENSE
+static FONT_DATA: &[u8] = include_bytes!("../fonts/DejaVuSerifCondensed.ttf");
+const FONT_NAME: &str = "DejaVuSerifCondensed";
+
+/// Name of the font in the resources of content that draws text
+pub(crate) const RESOURCE_NAME: &str = "Text";
+
+// Character drawn in place of characters missing from the font
+const FALLBACK: char = '?';
+
+// Maximum number of entries in each block of a cmap
+const CMAP_BLOCK: usize = 100;
+
+// Number of lines each curve of an outline is flattened into
+const CURVE_STEPS: u32 = 16;
+
+// Tables kept in the embedded font, the ones pdf readers draw glyphs with,
+// sorted by tag
+const SUBSET_TABLES: [&[u8; 4]; 11] = [
+    b"OS/2", b"cmap", b"cvt ", b"fpgm", b"glyf", b"head", b"hhea", b"hmtx", b"loca", b"maxp",
+    b"prep",
+];
+// Flags of the components of composite glyphs
+const ARGS_ARE_WORDS: u16 = 0x0001;
+const HAS_SCALE: u16 = 0x0008;
+const MORE_COMPONENTS: u16 = 0x0020;
+const HAS_X_AND_Y_SCALE: u16 = 0x0040;
+const HAS_TWO_BY_TWO: u16 = 0x0080;
+
+/// Font embedded in the pdf for text drawn on pages, which keeps track of the
+/// glyphs it has been used with
+pub(crate) struct Font {
+    face: Face<'static>,
+    // Character of each glyph used so far
+    used: BTreeMap<u16, char>,
+}
+
+impl Font {
+    pub fn new() -> Result<Self> {
+        let face =
+            Face::parse(FONT_DATA, 0).map_err(|err| anyhow!("could not parse font: {err}"))?;
+        Ok(Font {
+            face,
+            used: BTreeMap::new(),
+        })
+    }
+
+    fn glyph(&self, c: char) -> GlyphId {
+        (self.face.glyph_index(c))
+            .or_else(|| self.face.glyph_index(FALLBACK))
+            .unwrap_or(GlyphId(0))
+    }
+
+    /// Advance width of a glyph in thousandths of the font size
+    fn advance(&self, glyph: GlyphId) -> f32 {
+        let advance = self.face.glyph_hor_advance(glyph).unwrap_or(0);
+        self.scale(advance as i16)
+    }
+
+    /// Convert font units to thousandths of the font size
+    fn scale(&self, units: i16) -> f32 {
+        units as f32 * 1000.0 / self.face.units_per_em() as f32
+    }
+
+    /// Width of `text` in points when drawn at `size`
+    pub fn width(&self, text: &str, size: f32) -> f32 {
+        let width = text
+            .chars()
+            .map(|c| self.advance(self.glyph(c)))
+            .sum::<f32>();
+        width * size / 1000.0
+    }
+
+    /// Height above the baseline of capital letters, as a fraction of the
+    /// font size
+    pub fn cap_height(&self) -> f32 {
+        // Older fonts don't record the cap height, but it can be measured
+        let height = (self.face.capital_height())
+            .or_else(|| {
+                let glyph = self.face.glyph_index('H')?;
+                Some(self.face.glyph_bounding_box(glyph)?.y_max)
+            })
+            .unwrap_or(self.face.ascender());
+        self.scale(height) / 1000.0
+    }
+
+    /// Encode `text` as a string of glyph ids for the `Tj` operator
+    pub fn encode(&mut self, text: &str) -> Object {
+        let mut bytes = Vec::with_capacity(text.len() * 2);
+        for c in text.chars() {
+            let glyph = self.glyph(c);
+            let c = if self.face.glyph_index(c).is_some() {
+                c
+            } else {
+                FALLBACK
+            };
+            self.used.entry(glyph.0).or_insert(c);
+            bytes.extend(glyph.0.to_be_bytes());
+        }
+        Object::String(bytes, StringFormat::Hexadecimal)
+    }
+
+    /// Outlines of `text` drawn at `size`, starting at `(x, y)` on the
+    /// baseline, as closed polygons with curves flattened into lines
+    pub fn outlines(&self, text: &str, size: f32, (x, y): (f32, f32)) -> Vec<Vec<(f32, f32)>> {
+        let mut outline = Outline {
+            polygons: Vec::new(),
+            scale: size / self.face.units_per_em() as f32,
+            origin: (x, y),
+        };
+        for c in text.chars() {
+            let glyph = self.glyph(c);
+            self.face.outline_glyph(glyph, &mut outline);
+            outline.origin.0 += self.advance(glyph) * size / 1000.0;
+        }
+        outline.polygons
+    }
+
+    /// Operations drawing `text` at `size`, starting at `(x, y)` on the
+    /// baseline
+    pub fn draw(&mut self, text: &str, size: f32, (x, y): (f32, f32)) -> Vec<Operation> {
+        vec![
+            Operation::new("BT", vec![]),
+            Operation::new("Tf", vec![RESOURCE_NAME.into(), size.into()]),
+            Operation::new("Td", vec![x.into(), y.into()]),
+            Operation::new("Tj", vec![self.encode(text)]),
+            Operation::new("ET", vec![]),
+        ]
+    }
+
+    /// Write the font to the pdf as the object `id`, with the widths of the
+    /// glyphs it has been used with
+    pub fn write<W: Write>(&self, pdf: &mut PdfWriter<W>, id: ObjectId) -> Result<()> {
+        let data = self.subset()?;
+        let mut file = Stream::new(dictionary! { "Length1" => data.len() as i64 }, data);
+        file.compress()?;
+        let file_id = pdf.add(file)?;
+
+        let bbox = self.face.global_bounding_box();
+        let descriptor_id = pdf.add(dictionary! {
+            "Type" => "FontDescriptor",
+            "FontName" => FONT_NAME,
+            // Serif and nonsymbolic
+            "Flags" => 2 | 32,
+            "FontBBox" => vec![
+                self.scale(bbox.x_min).into(),
+                self.scale(bbox.y_min).into(),
+                self.scale(bbox.x_max).into(),
+                self.scale(bbox.y_max).into(),
+            ],
+            "ItalicAngle" => self.face.italic_angle(),
+            "Ascent" => self.scale(self.face.ascender()),
+            "Descent" => self.scale(self.face.descender()),
+            "CapHeight" => self.cap_height() * 1000.0,
+            "StemV" => 80,
+            "FontFile2" => file_id,
+        })?;
+
+        // Widths of used glyphs, each as `gid [width]`
+        let mut widths = Vec::new();
+        for &glyph in self.used.keys() {
+            widths.push(Object::from(glyph as i64));
+            widths.push(vec![self.advance(GlyphId(glyph)).round().into()].into());
+        }
+        let descendant_id = pdf.add(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "CIDFontType2",
+            "BaseFont" => FONT_NAME,
+            "CIDSystemInfo" => dictionary! {
+                "Registry" => Object::string_literal("Adobe"),
+                "Ordering" => Object::string_literal("Identity"),
+                "Supplement" => 0,
+            },
+            "FontDescriptor" => descriptor_id,
+            "W" => widths,
+            "CIDToGIDMap" => "Identity",
+        })?;
+
+        let mut to_unicode = Stream::new(dictionary! {}, self.to_unicode().into_bytes());
+        to_unicode.compress()?;
+        let to_unicode_id = pdf.add(to_unicode)?;
+
+        pdf.set(
+            id,
+            dictionary! {
+                "Type" => "Font",
+                "Subtype" => "Type0",
+                "BaseFont" => FONT_NAME,
+                "Encoding" => "Identity-H",
+                "DescendantFonts" => vec![descendant_id.into()],
+                "ToUnicode" => to_unicode_id,
+            },
+        )?;
+        Ok(())
+    }
+
+    /// Cmap from glyph ids to unicode, so text in the pdf can be copied and
+    /// searched
+    fn to_unicode(&self) -> String {
+        let mut cmap = String::from(
+            "/CIDInit /ProcSet findresource begin\n\
+             12 dict begin\n\
+             begincmap\n\
+             /CIDSystemInfo << /Registry (Adobe) /Ordering (UCS) /Supplement 0 >> def\n\
+             /CMapName /Adobe-Identity-UCS def\n\
+             /CMapType 2 def\n\
+             1 begincodespacerange\n\
+             <0000> <FFFF>\n\
+             endcodespacerange\n",
+        );
+        let used = self.used.iter().collect::<Vec<_>>();
+        for block in used.chunks(CMAP_BLOCK) {
+            let _ = writeln!(cmap, "{} beginbfchar", block.len());
+            for &(&glyph, &c) in block {
+                let mut utf16 = [0; 2];
+                let unicode = (c.encode_utf16(&mut utf16).iter())
+                    .map(|unit| format!("{unit:04X}"))
+                    .collect::<String>();
+                let _ = writeln!(cmap, "<{glyph:04X}> <{unicode}>");
+            }
+            cmap.push_str("endbfchar\n");
+        }
+        cmap.push_str(
+            "endcmap\n\
+             CMapName currentdict /CMap defineresource pop\n\
+             end\n\
+             end\n",
+        );
+        cmap
+    }
+
+    /// The font with only the tables needed to draw it, and the outlines of
+    /// glyphs it hasn't been used with left out. Pdfs embed this instead of
+    /// the whole 340kB font, which would outweigh most text proxies. Glyph
+    /// ids are kept, so text doesn't need to be encoded again
+    fn subset(&self) -> Result<Vec<u8>> {
+        let invalid = || anyhow!("invalid font data");
+        let table = |tag: &[u8; 4]| {
+            let count = read_u16(FONT_DATA, 4)? as usize;
+            let at = (0..count)
+                .map(|i| 12 + 16 * i)
+                .find(|&at| FONT_DATA.get(at..at + 4) == Some(tag))?;
+            let offset = read_u32(FONT_DATA, at + 8)? as usize;
+            let length = read_u32(FONT_DATA, at + 12)? as usize;
+            FONT_DATA.get(offset..offset + length)
+        };
+
+        // Outline of each glyph
+        let head = table(b"head").ok_or_else(invalid)?;
+        let loca = table(b"loca").ok_or_else(invalid)?;
+        let glyf = table(b"glyf").ok_or_else(invalid)?;
+        let glyph_count = table(b"maxp")
+            .and_then(|maxp| read_u16(maxp, 4))
+            .ok_or_else(invalid)?;
+        let long_offsets = read_u16(head, 50).ok_or_else(invalid)? != 0;
+        let glyph = |id: u16| {
+            let offset = |i: usize| match long_offsets {
+                true => read_u32(loca, 4 * i).map(|offset| offset as usize),
+                false => read_u16(loca, 2 * i).map(|offset| 2 * offset as usize),
+            };
+            let (start, end) = (offset(id as usize)?, offset(id as usize + 1)?);
+            glyf.get(start..end)
+        };
+
+        // Used glyphs, the glyphs composite ones are made of, and the
+        // missing glyph
+        let mut kept = BTreeSet::new();
+        let mut pending: Vec<u16> = self.used.keys().copied().chain([0]).collect();
+        while let Some(id) = pending.pop() {
+            if !kept.insert(id) {
+                continue;
+            }
+            let data = glyph(id).ok_or_else(invalid)?;
+            if data.len() < 10 || read_u16(data, 0).ok_or_else(invalid)? as i16 >= 0 {
+                continue;
+            }
+            let mut at = 10;
+            loop {
+                let flags = read_u16(data, at).ok_or_else(invalid)?;
+                pending.push(read_u16(data, at + 2).ok_or_else(invalid)?);
+                at += 4 + if flags & ARGS_ARE_WORDS != 0 { 4 } else { 2 };
+                at += if flags & HAS_SCALE != 0 {
+                    2
+                } else if flags & HAS_X_AND_Y_SCALE != 0 {
+                    4
+                } else if flags & HAS_TWO_BY_TWO != 0 {
+                    8
+                } else {
+                    0
+                };
+                if flags & MORE_COMPONENTS == 0 {
+                    break;
+                }
+            }
+        }
+
+        let mut new_glyf = Vec::new();
+        let mut new_loca = Vec::new();
+        for id in 0..glyph_count {
+            new_loca.extend((new_glyf.len() as u32).to_be_bytes());
+            if kept.contains(&id) {
+                new_glyf.extend_from_slice(glyph(id).ok_or_else(invalid)?);
+                new_glyf.resize(new_glyf.len().next_multiple_of(4), 0);
+            }
+        }
+        new_loca.extend((new_glyf.len() as u32).to_be_bytes());
+        // Offsets are always long, and the checksum is filled in at the end
+        let mut new_head = head.to_vec();
+        new_head[8..12].fill(0);
+        new_head[50..52].copy_from_slice(&1u16.to_be_bytes());
+
+        let tables = SUBSET_TABLES
+            .into_iter()
+            .map(|tag| {
+                let data = match tag {
+                    b"glyf" => &new_glyf[..],
+                    b"loca" => &new_loca[..],
+                    b"head" => &new_head[..],
+                    _ => table(tag).ok_or_else(invalid)?,
+                };
+                Ok((tag, data))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // Table directory followed by the tables, each padded to 4 bytes
+        let count = tables.len() as u16;
+        let search_range = 16u16 << count.ilog2();
+        let mut font = Vec::new();
+        font.extend(0x0001_0000u32.to_be_bytes());
+        font.extend(count.to_be_bytes());
+        font.extend(search_range.to_be_bytes());
+        font.extend((count.ilog2() as u16).to_be_bytes());
+        font.extend((count * 16 - search_range).to_be_bytes());
+        let mut offset = 12 + 16 * tables.len();
+        for (tag, data) in &tables {
+            font.extend_from_slice(&tag[..]);
+            font.extend(checksum(data).to_be_bytes());
+            font.extend((offset as u32).to_be_bytes());
+            font.extend((data.len() as u32).to_be_bytes());
+            offset += data.len().next_multiple_of(4);
+        }
+        let mut head_offset = 0;
+        for (tag, data) in &tables {
+            if tag == &b"head" {
+                head_offset = font.len();
+            }
+            font.extend_from_slice(data);
+            font.resize(font.len().next_multiple_of(4), 0);
+        }
+        let adjustment = 0xb1b0_afbau32.wrapping_sub(checksum(&font));
+        font[head_offset + 8..head_offset + 12].copy_from_slice(&adjustment.to_be_bytes());
+        Ok(font)
+    }
+}
+
+/// Polygons of glyph outlines, in points relative to the page
+struct Outline {
+    polygons: Vec<Vec<(f32, f32)>>,
+    // Points per font unit
+    scale: f32,
+    // Start of the current glyph on the baseline
+    origin: (f32, f32),
+}
+
+impl Outline {
+    fn point(&self, x: f32, y: f32) -> (f32, f32) {
+        (
+            self.origin.0 + x * self.scale,
+            self.origin.1 + y * self.scale,
+        )
+    }
+
+    /// Add a curve from the last point, with its points at `t` from 0 to 1
+    /// given by `at`
+    fn flatten(&mut self, at: impl Fn((f32, f32), f32) -> (f32, f32)) {
+        let Some(polygon) = self.polygons.last_mut() else {
+            return;
+        };
+        let Some(&start) = polygon.last() else {
+            return;
+        };
+        for step in 1..=CURVE_STEPS {
+            polygon.push(at(start, step as f32 / CURVE_STEPS as f32));
+        }
+    }
+}
+
+impl OutlineBuilder for Outline {
+    fn move_to(&mut self, x: f32, y: f32) {
+        let point = self.point(x, y);
+        self.polygons.push(vec![point]);
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        let point = self.point(x, y);
+        if let Some(polygon) = self.polygons.last_mut() {
+            polygon.push(point);
+        }
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        let (p1, p2) = (self.point(x1, y1), self.point(x, y));
+        self.flatten(|p0, t| {
+            let u = 1.0 - t;
+            let weights = [u * u, 2.0 * u * t, t * t];
+            let x = weights[0] * p0.0 + weights[1] * p1.0 + weights[2] * p2.0;
+            let y = weights[0] * p0.1 + weights[1] * p1.1 + weights[2] * p2.1;
+            (x, y)
+        });
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        let (p1, p2, p3) = (self.point(x1, y1), self.point(x2, y2), self.point(x, y));
+        self.flatten(|p0, t| {
+            let u = 1.0 - t;
+            let weights = [u * u * u, 3.0 * u * u * t, 3.0 * u * t * t, t * t * t];
+            let x = weights[0] * p0.0 + weights[1] * p1.0 + weights[2] * p2.0 + weights[3] * p3.0;
+            let y = weights[0] * p0.1 + weights[1] * p1.1 + weights[2] * p2.1 + weights[3] * p3.1;
+            (x, y)
+        });
+    }
+
+    // Polygons are always closed
+    fn close(&mut self) {}
+}
+
+fn read_u16(data: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(data.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn read_u32(data: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(at..at + 4)?.try_into().ok()?))
+}
+
+/// Checksum of a font table, the sum of its bytes as 32 bit integers
+fn checksum(data: &[u8]) -> u32 {
+    (data.chunks(4))
+        .map(|chunk| {
+            let mut word = [0; 4];
+            word[..chunk.len()].copy_from_slice(chunk);
+            u32::from_be_bytes(word)
+        })
+        .fold(0, u32::wrapping_add)
+}
@@ -1,4 +1,11 @@
+pub mod adjust;
+pub mod color;
+pub mod error;
+mod font;
+mod pdf;
 pub mod print;
+pub mod progress;
+mod zip;
 
 use wasm_bindgen::prelude::*;
 
@@ -0,0 +1,728 @@
+use anyhow::{Context, Result, bail};
+use mtg_print::{
+    adjust::Adjustments,
+    color::ColorMode,
+    print::{
+        FitMode, Orientation, OutputFormat, PaperSize, PrintJob, RenderMode, ResampleFilter,
+        StampPosition,
+    },
+};
+use std::{
+    collections::HashMap,
+    env,
+    fs::{self, File},
+    io::BufWriter,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::Arc,
+};
+
+const USAGE: &str = "\
+Generate a printable pdf of proxy cards from a decklist and a directory of card images
+
+Usage: mtg-print [OPTIONS] <DECKLIST> <IMAGE_DIR>
+
+Each decklist line is a count and a card name, e.g. `4 Ledger Shredder (SNC) 46`. Images
+are matched by file name, either `<name>-<set>-<collector number>` or `<name>`, ignoring
+case and punctuation (e.g. `ledger-shredder-snc-46.png` or `Ledger Shredder.jpg`).
+
+Options:
+  -o, --output <PATH>     Output file [default: out.pdf, or out.zip for other formats]
+  -f, --format <FORMAT>   Output format: pdf, images (card images with bleed), or png,
+                          jpeg, tiff, svg (page sheets) [default: pdf]. Other formats
+                          than pdf are zipped
+      --dpi <DPI>         Resolution of card images and sheets, from 72 to 1200, or
+                          native to keep the resolution of each image [default: 300]
+      --resample <FILTER> Filter card images are resampled with: lanczos3, catmull-rom,
+                          nearest, none [default: catmull-rom]
+      --fit <MODE>        How images with a different aspect ratio than their card are
+                          fitted to it: stretch, fit, fill [default: stretch]
+      --auto-crop         Straighten scanned card images and crop them to the card
+  -p, --paper <SIZE>      Paper size: letter, legal, a4, a3, tabloid [default: letter]
+      --landscape         Use landscape orientation
+  -c, --copies <N>        Number of copies of the decklist to print [default: 1]
+  -s, --split <PAGES>     Split output into multiple files of at most PAGES pages each,
+                          named <OUTPUT>-1.pdf, <OUTPUT>-2.pdf, ... (or .zip)
+      --scale <SCALE>     Scale correction for the printer, e.g. 0.995 [default: 1]
+      --gutter <MM>       Space between cards in millimetres, from 0 to 10 [default: 0]
+      --card-back <PATH>  Image printed on the back of every card
+      --print-friendly    Brighten, saturate and sharpen images for home inkjet printers
+      --grayscale         Print cards in grayscale
+      --line-art          Print cards in black and white only
+      --thin-border       Replace the black frame of each card with white to save ink
+      --srgb              Embed an sRGB output intent for professional printing
+      --cmyk <PROFILE>    Convert images to CMYK with an ICC profile from the print shop
+      --footer            Print page numbers and card names below the cards
+      --stamp <TEXT>      Stamp text on every card, e.g. PROXY
+      --stamp-logo <PATH> Stamp a logo image on every card instead of text
+      --stamp-at <POS>    Stamp position: center, text-box, top-left, top-right,
+                          bottom-left, bottom-right [default: center]
+      --stamp-opacity <N> Stamp opacity from 0 to 1 [default: 0.5]
+      --stamp-angle <DEG> Counterclockwise rotation of the stamp in degrees [default: 0]
+  -h, --help              Print help";
+
+struct Args {
+    decklist: PathBuf,
+    image_dir: PathBuf,
+    output: PathBuf,
+    format: OutputFormat,
+    dpi: Option<u32>,
+    resample_filter: ResampleFilter,
+    fit_mode: FitMode,
+    auto_crop: bool,
+    paper: PaperSize,
+    orientation: Orientation,
+    copies: u32,
+    split: Option<u32>,
+    scale: f32,
+    gutter: f32,
+    card_back: Option<PathBuf>,
+    print_friendly: bool,
+    color_mode: ColorMode,
+    icc_profile: Option<PathBuf>,
+    render_mode: RenderMode,
+    thin_border: bool,
+    footer: bool,
+    stamp: Option<String>,
+    stamp_logo: Option<PathBuf>,
+    stamp_position: StampPosition,
+    stamp_opacity: f32,
+    stamp_rotation: f32,
+}
+
+impl Args {
+    /// Parse command line arguments, returning `None` if help was requested
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Option<Self>> {
+        let mut positional = Vec::new();
+        let mut output = None;
+        let mut format = OutputFormat::Pdf;
+        let mut dpi = None;
+        let mut resample_filter = ResampleFilter::CatmullRom;
+        let mut fit_mode = FitMode::Stretch;
+        let mut auto_crop = false;
+        let mut paper = PaperSize::Letter;
+        let mut orientation = Orientation::Portrait;
+        let mut copies = 1;
+        let mut split = None;
+        let mut scale = 1.0;
+        let mut gutter = 0.0;
+        let mut card_back = None;
+        let mut print_friendly = false;
+        let mut color_mode = ColorMode::DeviceRgb;
+        let mut icc_profile = None;
+        let mut render_mode = RenderMode::Color;
+        let mut thin_border = false;
+        let mut footer = false;
+        let mut stamp = None;
+        let mut stamp_logo = None;
+        let mut stamp_position = StampPosition::Center;
+        let mut stamp_opacity = 0.5;
+        let mut stamp_rotation = 0.0;
+
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .with_context(|| format!("missing value for {arg}"))
+            };
+            match arg.as_str() {
+                "-h" | "--help" => return Ok(None),
+                "-o" | "--output" => output = Some(PathBuf::from(value()?)),
+                "-f" | "--format" => format = value()?.parse()?,
+                "--dpi" => match value()?.as_str() {
+                    "native" => resample_filter = ResampleFilter::Native,
+                    value => dpi = Some(value.parse().context("invalid dpi")?),
+                },
+                "--resample" => resample_filter = value()?.parse()?,
+                "--fit" => fit_mode = value()?.parse()?,
+                "--auto-crop" => auto_crop = true,
+                "-p" | "--paper" => paper = value()?.parse()?,
+                "--landscape" => orientation = Orientation::Landscape,
+                "-c" | "--copies" => copies = value()?.parse().context("invalid copies")?,
+                "-s" | "--split" => {
+                    let pages = value()?.parse().context("invalid split")?;
+                    if pages == 0 {
+                        bail!("split must be at least 1 page");
+                    }
+                    split = Some(pages);
+                }
+                "--scale" => scale = value()?.parse().context("invalid scale")?,
+                "--gutter" => gutter = value()?.parse().context("invalid gutter")?,
+                "--card-back" => card_back = Some(PathBuf::from(value()?)),
+                "--print-friendly" => print_friendly = true,
+                "--grayscale" => render_mode = RenderMode::Grayscale,
+                "--line-art" => render_mode = RenderMode::LineArt,
+                "--thin-border" => thin_border = true,
+                "--footer" => footer = true,
+                "--stamp" => stamp = Some(value()?),
+                "--stamp-logo" => stamp_logo = Some(PathBuf::from(value()?)),
+                "--stamp-at" => stamp_position = value()?.parse()?,
+                "--stamp-opacity" => {
+                    stamp_opacity = value()?.parse().context("invalid stamp opacity")?
+                }
+                "--stamp-angle" => {
+                    stamp_rotation = value()?.parse().context("invalid stamp angle")?
+                }
+                "--srgb" => color_mode = ColorMode::Srgb,
+                "--cmyk" => {
+                    color_mode = ColorMode::Cmyk;
+                    icc_profile = Some(PathBuf::from(value()?));
+                }
+                _ if arg.starts_with('-') => bail!("unknown option {arg}"),
+                _ => positional.push(PathBuf::from(arg)),
+            }
+        }
+
+        let [decklist, image_dir] = <[PathBuf; 2]>::try_from(positional)
+            .map_err(|_| anyhow::anyhow!("expected <DECKLIST> and <IMAGE_DIR>"))?;
+        let output = output.unwrap_or_else(|| match format {
+            OutputFormat::Pdf => PathBuf::from("out.pdf"),
+            _ => PathBuf::from("out.zip"),
+        });
+        Ok(Some(Args {
+            decklist,
+            image_dir,
+            output,
+            format,
+            dpi,
+            resample_filter,
+            fit_mode,
+            auto_crop,
+            paper,
+            orientation,
+            copies,
+            split,
+            scale,
+            gutter,
+            card_back,
+            print_friendly,
+            color_mode,
+            icc_profile,
+            render_mode,
+            thin_border,
+            footer,
+            stamp,
+            stamp_logo,
+            stamp_position,
+            stamp_opacity,
+            stamp_rotation,
+        }))
+    }
+}
+
+/// A line of a decklist
+struct Entry {
+    count: u32,
+    name: String,
+    set: Option<String>,
+    collector_number: Option<String>,
+}
+
+/// Parse decklists in the common `4 Card Name (SET) 123` format, skipping
+/// blank lines, comments and section headers
+fn parse_decklist(text: &str) -> Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with("//") {
+            continue;
+        }
+        let (count, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let Ok(count) = count.trim_end_matches(['x', 'X']).parse::<u32>() else {
+            // Section headers like `Sideboard`
+            continue;
+        };
+        let rest = rest.trim();
+        if rest.is_empty() {
+            bail!("line {}: missing card name", i + 1);
+        }
+
+        // Optional `(SET) 123` suffix
+        let (name, set, collector_number) = match rest.rsplit_once(" (") {
+            Some((name, suffix)) => match suffix.split_once(')') {
+                Some((set, number)) => {
+                    let number = number.split_whitespace().next().map(str::to_string);
+                    (name, Some(set.to_string()), number)
+                }
+                None => bail!("line {}: unclosed set code", i + 1),
+            },
+            None => (rest, None, None),
+        };
+        entries.push(Entry {
+            count,
+            name: name.trim().to_string(),
+            set,
+            collector_number,
+        });
+    }
+    Ok(entries)
+}
+
+/// Normalize a card name or file name for matching
+fn normalize(name: &str) -> String {
+    let mut output = String::new();
+    for word in name.split(|c: char| !c.is_alphanumeric()) {
+        if !word.is_empty() {
+            if !output.is_empty() {
+                output.push('-');
+            }
+            output.push_str(&word.to_lowercase());
+        }
+    }
+    output
+}
+
+/// Index the images in a directory by normalized file name
+fn index_images(dir: &Path) -> Result<HashMap<String, PathBuf>> {
+    let entries = fs::read_dir(dir).with_context(|| format!("could not read {}", dir.display()))?;
+    let paths = entries
+        .map(|entry| Ok(entry?.path()))
+        .collect::<Result<Vec<_>>>()?;
+    index_paths(paths)
+}
+
+/// Index image files by normalized file name, skipping other files. Two
+/// images with the same name are an error, since either could be printed
+fn index_paths(paths: Vec<PathBuf>) -> Result<HashMap<String, PathBuf>> {
+    let mut images = HashMap::<_, PathBuf>::new();
+    for path in paths {
+        let is_image = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| {
+                matches!(ext.to_lowercase().as_str(), "png" | "jpg" | "jpeg" | "webp")
+            });
+        let (true, Some(stem)) = (is_image, path.file_stem().and_then(|s| s.to_str())) else {
+            continue;
+        };
+        let name = normalize(stem);
+        if let Some(other) = images.get(&name) {
+            // Directories are read in no particular order
+            let mut both = [other.display().to_string(), path.display().to_string()];
+            both.sort();
+            bail!("{} and {} are both images of {name}", both[0], both[1]);
+        }
+        images.insert(name, path);
+    }
+    Ok(images)
+}
+
+/// Find the image for a decklist entry, preferring an exact printing
+fn find_image<'a>(images: &'a HashMap<String, PathBuf>, entry: &Entry) -> Option<&'a PathBuf> {
+    // Double faced cards are matched by their front face
+    let name = entry.name.split(" // ").next().unwrap_or(&entry.name);
+    let printing = match (&entry.set, &entry.collector_number) {
+        (Some(set), Some(number)) => Some(format!("{name} {set} {number}")),
+        (Some(set), None) => Some(format!("{name} {set}")),
+        _ => None,
+    };
+    printing
+        .and_then(|printing| images.get(&normalize(&printing)))
+        .or_else(|| images.get(&normalize(&entry.name)))
+        .or_else(|| images.get(&normalize(name)))
+}
+
+/// Find the image of every decklist entry, returning each card's count
+/// multiplied by `copies`, its name and its image. Fails with the names of all
+/// cards without an image
+fn find_cards<'a>(
+    entries: &'a [Entry],
+    images: &'a HashMap<String, PathBuf>,
+    copies: u32,
+) -> Result<Vec<(u32, &'a str, &'a Path)>> {
+    let mut cards = Vec::new();
+    let mut missing = Vec::new();
+    for entry in entries {
+        let count = (entry.count.checked_mul(copies))
+            .with_context(|| format!("too many copies of {}", entry.name))?;
+        match find_image(images, entry) {
+            Some(path) => cards.push((count, entry.name.as_str(), path.as_path())),
+            None => missing.push(entry.name.as_str()),
+        }
+    }
+    if !missing.is_empty() {
+        bail!("could not find images for: {}", missing.join(", "));
+    }
+    Ok(cards)
+}
+
+/// Partition cards with the given counts into jobs of at most `job_size`
+/// cards each, returning the index and count of the cards in each job. Cards
+/// at the end of a job continue in the next one
+fn split_jobs(counts: &[u32], job_size: u32) -> Vec<Vec<(usize, u32)>> {
+    let mut jobs = vec![Vec::new()];
+    let mut remaining = job_size;
+    for (idx, mut count) in counts.iter().copied().enumerate() {
+        while count > 0 {
+            if remaining == 0 {
+                jobs.push(Vec::new());
+                remaining = job_size;
+            }
+            let n = count.min(remaining);
+            jobs.last_mut().unwrap().push((idx, n));
+            count -= n;
+            remaining -= n;
+        }
+    }
+    jobs
+}
+
+fn read(path: &Path) -> Result<Arc<[u8]>> {
+    let data = fs::read(path).with_context(|| format!("could not read {}", path.display()))?;
+    Ok(Arc::from(data))
+}
+
+/// Files given as options, read once and shared by every job
+#[derive(Default)]
+struct OptionFiles {
+    card_back: Option<Arc<[u8]>>,
+    icc_profile: Option<Arc<[u8]>>,
+    stamp_logo: Option<Arc<[u8]>>,
+}
+
+impl OptionFiles {
+    fn read(args: &Args) -> Result<Self> {
+        Ok(OptionFiles {
+            card_back: args.card_back.as_deref().map(read).transpose()?,
+            icc_profile: args.icc_profile.as_deref().map(read).transpose()?,
+            stamp_logo: args.stamp_logo.as_deref().map(read).transpose()?,
+        })
+    }
+}
+
+/// Create a print job without cards, set up from the options
+fn new_job(args: &Args, files: &OptionFiles) -> Result<PrintJob> {
+    let mut job = PrintJob::new();
+    // Pdfs are titled after the decklist
+    if let Some(stem) = args.decklist.file_stem() {
+        job.set_title(stem.to_string_lossy().into_owned());
+    }
+    job.set_paper_size(args.paper);
+    job.set_orientation(args.orientation);
+    job.set_scale(args.scale).map_err(anyhow::Error::msg)?;
+    job.set_gutter(args.gutter).map_err(anyhow::Error::msg)?;
+    if let Some(card_back) = &files.card_back {
+        job.set_card_back(Box::from(&**card_back));
+    }
+    let profile = files
+        .icc_profile
+        .as_ref()
+        .map(|profile| Box::from(&**profile));
+    job.set_color_mode(args.color_mode, profile)
+        .map_err(anyhow::Error::msg)?;
+    job.set_render_mode(args.render_mode);
+    job.set_thin_border(args.thin_border);
+    job.set_footer(args.footer);
+    if let Some(text) = &args.stamp {
+        job.set_stamp_text(text.clone());
+    }
+    if let Some(logo) = &files.stamp_logo {
+        job.set_stamp_logo(Box::from(&**logo))
+            .map_err(anyhow::Error::msg)?;
+    }
+    job.set_stamp_style(args.stamp_position, args.stamp_opacity, args.stamp_rotation)
+        .map_err(anyhow::Error::msg)?;
+    job.set_output_format(args.format);
+    if let Some(dpi) = args.dpi {
+        job.set_dpi(dpi).map_err(anyhow::Error::msg)?;
+    }
+    job.set_resample_filter(args.resample_filter);
+    job.set_fit_mode(args.fit_mode);
+    job.set_auto_crop(args.auto_crop);
+    if args.print_friendly {
+        job.set_adjustments(&Adjustments::print_friendly())
+            .map_err(anyhow::Error::msg)?;
+    }
+    Ok(job)
+}
+
+fn run(args: Args) -> Result<()> {
+    let text = fs::read_to_string(&args.decklist)
+        .with_context(|| format!("could not read {}", args.decklist.display()))?;
+    let entries = parse_decklist(&text)?;
+    if entries.is_empty() {
+        bail!("no cards found in {}", args.decklist.display());
+    }
+    let images = index_images(&args.image_dir)?;
+    let cards = find_cards(&entries, &images, args.copies)?;
+    let data = (cards.iter())
+        .map(|&(_, _, path)| read(path))
+        .collect::<Result<Vec<_>>>()?;
+    let files = OptionFiles::read(&args)?;
+
+    let job_size = match args.split {
+        Some(pages) => {
+            let per_page =
+                (new_job(&args, &files)?.cards_per_page()).map_err(anyhow::Error::msg)?;
+            pages * per_page
+        }
+        None => u32::MAX,
+    };
+    let counts = cards.iter().map(|&(count, _, _)| count).collect::<Vec<_>>();
+    let jobs = split_jobs(&counts, job_size);
+
+    let len = jobs.len();
+    for (i, job_cards) in jobs.into_iter().enumerate() {
+        let mut job = new_job(&args, &files)?;
+        for (added, (idx, count)) in job_cards.into_iter().enumerate() {
+            job.add_card(count, Box::from(&*data[idx]));
+            job.set_card_name(added as u32, cards[idx].1.to_string())
+                .map_err(anyhow::Error::msg)?;
+        }
+        let output = if len == 1 {
+            args.output.clone()
+        } else {
+            let stem = args
+                .output
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy();
+            let extension = match args.format {
+                OutputFormat::Pdf => "pdf",
+                _ => "zip",
+            };
+            args.output
+                .with_file_name(format!("{stem}-{}.{extension}", i + 1))
+        };
+        let file = File::create(&output)
+            .with_context(|| format!("could not create {}", output.display()))?;
+        job.print_to(BufWriter::new(file))?;
+        println!("Wrote {}", output.display());
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args = match Args::parse(env::args().skip(1)) {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            println!("{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        Err(err) => {
+            eprintln!("error: {err}\n\n{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+    if let Err(err) = run(args) {
+        eprintln!("error: {err:#}");
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Result<Option<Args>> {
+        Args::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn decklists_are_parsed_with_counts_and_printings() {
+        let text = "\
+# Main deck
+4 Ledger Shredder (SNC) 46
+2x Lim-Dûl's Vault
+
+Sideboard
+1 Delver of Secrets // Insectile Aberration (MID)
+";
+        let entries = parse_decklist(text).unwrap();
+        let entries = (entries.iter())
+            .map(|entry| {
+                (
+                    entry.count,
+                    entry.name.as_str(),
+                    entry.set.as_deref(),
+                    entry.collector_number.as_deref(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            entries,
+            [
+                (4, "Ledger Shredder", Some("SNC"), Some("46")),
+                (2, "Lim-Dûl's Vault", None, None),
+                (
+                    1,
+                    "Delver of Secrets // Insectile Aberration",
+                    Some("MID"),
+                    None
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_decklist_lines_name_the_line() {
+        let err = parse_decklist("1 Island\n4 Ledger Shredder (SNC 46")
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "line 2: unclosed set code");
+        let err = parse_decklist("// Deck\n\n4").err().unwrap();
+        assert_eq!(err.to_string(), "line 3: missing card name");
+        assert!(parse_decklist("Deck\nSideboard").unwrap().is_empty());
+    }
+
+    #[test]
+    fn cards_are_matched_to_images_by_name_and_printing() {
+        let images = [
+            "ledger-shredder-snc-46.png",
+            "Ledger Shredder.jpg",
+            "Delver of Secrets.png",
+        ]
+        .map(|file| {
+            (
+                normalize(file.rsplit_once('.').unwrap().0),
+                PathBuf::from(file),
+            )
+        })
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+        let entries = parse_decklist(
+            "4 Ledger Shredder (SNC) 46\n\
+            1 Ledger Shredder (SNC) 300\n\
+            2 Delver of Secrets // Insectile Aberration",
+        )
+        .unwrap();
+
+        // Counts are multiplied by the number of copies
+        let cards = find_cards(&entries, &images, 2).unwrap();
+        let cards = (cards.iter())
+            .map(|&(count, name, path)| (count, name, path.to_str().unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            cards,
+            [
+                (8, "Ledger Shredder", "ledger-shredder-snc-46.png"),
+                (2, "Ledger Shredder", "Ledger Shredder.jpg"),
+                (
+                    4,
+                    "Delver of Secrets // Insectile Aberration",
+                    "Delver of Secrets.png"
+                ),
+            ]
+        );
+
+        let entries = parse_decklist("1 Island\n1 Ledger Shredder\n1 Forest").unwrap();
+        let err = find_cards(&entries, &images, 1).unwrap_err();
+        assert_eq!(err.to_string(), "could not find images for: Island, Forest");
+        let entries = parse_decklist("2 Ledger Shredder").unwrap();
+        let err = find_cards(&entries, &images, u32::MAX).unwrap_err();
+        assert_eq!(err.to_string(), "too many copies of Ledger Shredder");
+    }
+
+    #[test]
+    fn images_with_the_same_name_are_rejected() {
+        let paths = ["Ledger Shredder.png", "notes.txt", "ledger-shredder.txt"];
+        let images = index_paths(paths.map(PathBuf::from).to_vec()).unwrap();
+        assert_eq!(images.len(), 1);
+        assert_eq!(
+            images["ledger-shredder"],
+            PathBuf::from("Ledger Shredder.png")
+        );
+
+        let paths = ["cards/fire-ice.jpg", "cards/Fire & Ice.png"];
+        let err = index_paths(paths.map(PathBuf::from).to_vec())
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "cards/Fire & Ice.png and cards/fire-ice.jpg are both images of fire-ice"
+        );
+    }
+
+    #[test]
+    fn cards_are_split_between_jobs() {
+        assert_eq!(split_jobs(&[4, 2], u32::MAX), [vec![(0, 4), (1, 2)]]);
+        assert_eq!(
+            split_jobs(&[12, 3, 9], 9),
+            [vec![(0, 9)], vec![(0, 3), (1, 3), (2, 3)], vec![(2, 6)]]
+        );
+        assert_eq!(split_jobs(&[0, 9], 9), [vec![(1, 9)]]);
+    }
+
+    #[test]
+    fn options_are_parsed() {
+        assert!(args(&["deck.txt", "--help"]).unwrap().is_none());
+        let parsed = args(&[
+            "deck.txt",
+            "images",
+            "-f",
+            "png",
+            "--dpi",
+            "native",
+            "-c",
+            "2",
+            "-s",
+            "3",
+            "--cmyk",
+            "shop.icc",
+            "--line-art",
+        ])
+        .unwrap()
+        .unwrap();
+        assert_eq!(parsed.decklist, PathBuf::from("deck.txt"));
+        assert_eq!(parsed.image_dir, PathBuf::from("images"));
+        assert_eq!(parsed.format, OutputFormat::PngSheets);
+        assert_eq!(parsed.output, PathBuf::from("out.zip"));
+        assert_eq!(parsed.dpi, None);
+        assert_eq!(parsed.resample_filter, ResampleFilter::Native);
+        assert_eq!((parsed.copies, parsed.split), (2, Some(3)));
+        assert_eq!(parsed.color_mode, ColorMode::Cmyk);
+        assert_eq!(parsed.icc_profile, Some(PathBuf::from("shop.icc")));
+        assert_eq!(parsed.render_mode, RenderMode::LineArt);
+
+        let parsed = args(&["deck.txt", "images", "--dpi", "600"])
+            .unwrap()
+            .unwrap();
+        assert_eq!(parsed.output, PathBuf::from("out.pdf"));
+        assert_eq!(parsed.dpi, Some(600));
+    }
+
+    #[test]
+    fn invalid_options_are_rejected() {
+        let error = |options: &[&str]| args(options).err().unwrap().to_string();
+        assert_eq!(error(&["deck.txt"]), "expected <DECKLIST> and <IMAGE_DIR>");
+        assert_eq!(
+            error(&["deck.txt", "images", "--copies"]),
+            "missing value for --copies"
+        );
+        assert_eq!(
+            error(&["deck.txt", "images", "--dpi", "high"]),
+            "invalid dpi"
+        );
+        assert_eq!(
+            error(&["deck.txt", "images", "-s", "0"]),
+            "split must be at least 1 page"
+        );
+        assert_eq!(
+            error(&["deck.txt", "images", "--bleed"]),
+            "unknown option --bleed"
+        );
+
+        // Values are validated when the job is created
+        let parsed = args(&["deck.txt", "images", "--dpi", "5000"])
+            .unwrap()
+            .unwrap();
+        let err = new_job(&parsed, &OptionFiles::default()).err().unwrap();
+        assert_eq!(err.to_string(), "dpi must be between 72 and 1200");
+    }
+
+    #[test]
+    fn options_set_up_the_job() {
+        let parsed = args(&["deck.txt", "images", "-p", "a4", "--landscape"])
+            .unwrap()
+            .unwrap();
+        let job = new_job(&parsed, &OptionFiles::default()).unwrap();
+        assert_eq!(job.cards_per_page(), Ok(8));
+
+        let parsed = args(&["deck.txt", "images", "--gutter", "5"])
+            .unwrap()
+            .unwrap();
+        let job = new_job(&parsed, &OptionFiles::default()).unwrap();
+        // The gutter leaves room for only 2 rows of cards on letter paper
+        assert_eq!(job.cards_per_page(), Ok(6));
+    }
+}
@@ -0,0 +1,189 @@
+use anyhow::Result;
+use lopdf::{Dictionary, Object, ObjectId, StringFormat};
+use std::io::Write;
+
+/// Minimal pdf writer that writes each object to the output as soon as it is
+/// added, so that objects don't need to be kept in memory until the end
+pub struct PdfWriter<W: Write> {
+    inner: W,
+    // Number of bytes written so far
+    position: usize,
+    // Byte offset of each object, indexed by object number - 1
+    offsets: Vec<Option<usize>>,
+}
+
+impl<W: Write> PdfWriter<W> {
+    pub fn new(inner: W) -> Result<Self> {
+        let mut writer = PdfWriter {
+            inner,
+            position: 0,
+            offsets: Vec::new(),
+        };
+        // Binary comment marks the file as containing binary data
+        writer.write_bytes(b"%PDF-1.5\n%\xe2\xe3\xcf\xd3\n")?;
+        Ok(writer)
+    }
+
+    /// Number of bytes written so far
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Reserve an object id for an object that will be written later
+    pub fn reserve(&mut self) -> ObjectId {
+        self.offsets.push(None);
+        (self.offsets.len() as u32, 0)
+    }
+
+    /// Write an object with a new id
+    pub fn add(&mut self, object: impl Into<Object>) -> Result<ObjectId> {
+        let id = self.reserve();
+        self.set(id, object)?;
+        Ok(id)
+    }
+
+    /// Write an object with a previously reserved id
+    pub fn set(&mut self, id: ObjectId, object: impl Into<Object>) -> Result<()> {
+        self.offsets[id.0 as usize - 1] = Some(self.position);
+        let mut buffer = format!("{} {} obj\n", id.0, id.1).into_bytes();
+        write_object(&mut buffer, &object.into());
+        buffer.extend_from_slice(b"\nendobj\n");
+        self.write_bytes(&buffer)
+    }
+
+    /// Write the cross reference table and trailer, and return the output
+    pub fn finish(mut self, trailer: Dictionary) -> Result<W> {
+        let xref_start = self.position;
+        let mut buffer = format!("xref\n0 {}\n", self.offsets.len() + 1).into_bytes();
+        buffer.extend_from_slice(b"0000000000 65535 f \n");
+        for offset in &self.offsets {
+            match offset {
+                Some(offset) => buffer.extend(format!("{offset:010} 00000 n \n").bytes()),
+                None => buffer.extend_from_slice(b"0000000000 00000 f \n"),
+            }
+        }
+        let mut trailer = trailer;
+        trailer.set("Size", self.offsets.len() as i64 + 1);
+        buffer.extend_from_slice(b"trailer\n");
+        write_object(&mut buffer, &Object::Dictionary(trailer));
+        buffer.extend(format!("\nstartxref\n{xref_start}\n%%EOF\n").bytes());
+        self.write_bytes(&buffer)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<()> {
+        self.inner.write_all(bytes)?;
+        self.position += bytes.len();
+        Ok(())
+    }
+}
+
+/// Text string for metadata and outlines, encoded as UTF-16 unless it is
+/// plain ASCII
+pub fn text_string(text: &str) -> Object {
+    if text.is_ascii() {
+        return Object::string_literal(text);
+    }
+    let mut bytes = vec![0xfe, 0xff];
+    for unit in text.encode_utf16() {
+        bytes.extend(unit.to_be_bytes());
+    }
+    Object::String(bytes, StringFormat::Hexadecimal)
+}
+
+/// Date string for `timestamp` seconds since the Unix epoch, in UTC
+pub fn date_string(timestamp: i64) -> Object {
+    let days = timestamp.div_euclid(86400);
+    let seconds = timestamp.rem_euclid(86400);
+    // Convert days to a civil date, see
+    // https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    Object::string_literal(format!(
+        "D:{year:04}{month:02}{day:02}{:02}{:02}{:02}Z",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+    ))
+}
+
+/// Serialize an object in pdf syntax
+fn write_object(buffer: &mut Vec<u8>, object: &Object) {
+    match object {
+        Object::Null => buffer.extend_from_slice(b"null"),
+        Object::Boolean(value) => buffer.extend(value.to_string().bytes()),
+        Object::Integer(value) => buffer.extend(value.to_string().bytes()),
+        Object::Real(value) => buffer.extend(value.to_string().bytes()),
+        Object::Name(name) => write_name(buffer, name),
+        Object::String(bytes, StringFormat::Literal) => {
+            buffer.push(b'(');
+            for &byte in bytes {
+                if matches!(byte, b'(' | b')' | b'\\') {
+                    buffer.push(b'\\');
+                }
+                buffer.push(byte);
+            }
+            buffer.push(b')');
+        }
+        Object::String(bytes, StringFormat::Hexadecimal) => {
+            buffer.push(b'<');
+            for byte in bytes {
+                buffer.extend(format!("{byte:02X}").bytes());
+            }
+            buffer.push(b'>');
+        }
+        Object::Array(array) => {
+            buffer.push(b'[');
+            for (i, item) in array.iter().enumerate() {
+                if i > 0 {
+                    buffer.push(b' ');
+                }
+                write_object(buffer, item);
+            }
+            buffer.push(b']');
+        }
+        Object::Dictionary(dict) => write_dictionary(buffer, dict),
+        Object::Stream(stream) => {
+            let mut dict = stream.dict.clone();
+            dict.set("Length", stream.content.len() as i64);
+            write_dictionary(buffer, &dict);
+            buffer.extend_from_slice(b"\nstream\n");
+            buffer.extend_from_slice(&stream.content);
+            buffer.extend_from_slice(b"\nendstream");
+        }
+        Object::Reference((id, generation)) => {
+            buffer.extend(format!("{id} {generation} R").bytes());
+        }
+    }
+}
+
+fn write_dictionary(buffer: &mut Vec<u8>, dict: &Dictionary) {
+    buffer.extend_from_slice(b"<<");
+    for (key, value) in dict.iter() {
+        write_name(buffer, key);
+        buffer.push(b' ');
+        write_object(buffer, value);
+    }
+    buffer.extend_from_slice(b">>");
+}
+
+fn write_name(buffer: &mut Vec<u8>, name: &[u8]) {
+    buffer.push(b'/');
+    for &byte in name {
+        // Delimiters, whitespace and non printable characters are hex escaped
+        if byte.is_ascii_graphic() && !b"#()<>[]{}/%".contains(&byte) {
+            buffer.push(byte);
+        } else {
+            buffer.extend(format!("#{byte:02X}").bytes());
+        }
+    }
+}
@@ -1,28 +1,463 @@
-use std::{io::Cursor, mem, sync::Arc};
+use std::{
+    io::{Cursor, Write},
+    str::FromStr,
+    sync::Arc,
+};
 
-use anyhow::{Context, Result};
+use anyhow::{Result, bail};
 use image::{
-    DynamicImage, GenericImageView, ImageReader, Rgba, RgbaImage,
-    imageops::{FilterType::CatmullRom, overlay},
+    DynamicImage, GenericImageView, ImageError, ImageFormat, ImageReader, Rgb, RgbImage, Rgba,
+    RgbaImage,
+    imageops::{self, FilterType},
 };
-use imageproc::{drawing::draw_filled_rect_mut, rect::Rect};
 use js_sys::Function;
 use lopdf::{
-    Document, Object, Stream,
+    Dictionary, Object, ObjectId, Stream,
     content::{Content, Operation},
     dictionary,
 };
 use wasm_bindgen::{JsValue, prelude::wasm_bindgen};
 
+mod crop;
+mod export;
+
+pub use export::OutputFormat;
+
+use crate::{
+    adjust::Adjustments,
+    color::{ColorMode, ColorOutput},
+    error::PrintError,
+    font::{self, Font},
+    pdf::{self, PdfWriter},
+    progress::{CancelToken, Phase, Progress},
+};
+
 struct Card {
-    data: Arc<[u8]>,
+    data: CardFace,
+    back: Option<CardFace>,
     count: u32,
+    // Overrides the adjustments of the job
+    adjustments: Option<Adjustments>,
+    size: CardSize,
+    // Listed in the outline and footer of the pages it is on
+    name: Option<String>,
+}
+
+/// Front or back of a card, either an image or a text proxy
+#[derive(Clone)]
+enum CardFace {
+    Image(Arc<[u8]>),
+    Text(Arc<TextCard>),
+}
+
+/// Card data drawn as a text-only proxy, for printing playtest cards without
+/// downloading images
+#[wasm_bindgen(getter_with_clone)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TextCard {
+    pub name: String,
+    /// Mana cost in Scryfall notation, e.g. `{2}{W/U}`
+    pub mana_cost: Option<String>,
+    pub type_line: Option<String>,
+    /// Rules text, with paragraphs separated by newlines
+    pub oracle_text: Option<String>,
+    pub power: Option<String>,
+    pub toughness: Option<String>,
+}
+
+#[wasm_bindgen]
+impl TextCard {
+    #[wasm_bindgen(constructor)]
+    pub fn new(name: String) -> Self {
+        TextCard {
+            name,
+            ..TextCard::default()
+        }
+    }
+}
+
+/// Standard paper sizes
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaperSize {
+    Letter,
+    Legal,
+    A4,
+    A3,
+    Tabloid,
+}
+
+impl PaperSize {
+    /// Portrait dimensions of the paper in points
+    pub fn dimensions(self) -> (f32, f32) {
+        match self {
+            PaperSize::Letter => (612.0, 792.0),
+            PaperSize::Legal => (612.0, 1008.0),
+            PaperSize::A4 => (595.28, 841.89),
+            PaperSize::A3 => (841.89, 1190.55),
+            PaperSize::Tabloid => (792.0, 1224.0),
+        }
+    }
+}
+
+impl FromStr for PaperSize {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "letter" => Ok(PaperSize::Letter),
+            "legal" => Ok(PaperSize::Legal),
+            "a4" => Ok(PaperSize::A4),
+            "a3" => Ok(PaperSize::A3),
+            "tabloid" => Ok(PaperSize::Tabloid),
+            _ => bail!("unknown paper size {s}"),
+        }
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Orientation {
+    Portrait,
+    Landscape,
+}
+
+/// Size and orientation of the pages in the output pdf
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PageSetup {
+    // Portrait dimensions of the paper in points
+    width: f32,
+    height: f32,
+    orientation: Orientation,
+}
+
+impl PageSetup {
+    pub fn new(paper: PaperSize, orientation: Orientation) -> Self {
+        let (width, height) = paper.dimensions();
+        PageSetup {
+            width,
+            height,
+            orientation,
+        }
+    }
+
+    /// Custom paper size, with dimensions given in points
+    pub fn custom(width: f32, height: f32, orientation: Orientation) -> Result<Self> {
+        let valid = |size: f32| size > 0.0 && size <= MAX_PAGE_SIZE;
+        if !(valid(width) && valid(height)) {
+            bail!("invalid paper size {width} x {height}");
+        }
+        Ok(PageSetup {
+            width: width.min(height),
+            height: width.max(height),
+            orientation,
+        })
+    }
+
+    /// Dimensions of the page in points after applying orientation
+    pub fn dimensions(&self) -> (f32, f32) {
+        match self.orientation {
+            Orientation::Portrait => (self.width, self.height),
+            Orientation::Landscape => (self.height, self.width),
+        }
+    }
+}
+
+impl Default for PageSetup {
+    fn default() -> Self {
+        PageSetup::new(PaperSize::Letter, Orientation::Portrait)
+    }
+}
+
+/// Edge of the paper that the printer flips around when printing double sided
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Duplex {
+    LongEdge,
+    ShortEdge,
+}
+
+/// How the bleed around each card is generated
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BleedMode {
+    /// Black rectangle behind each card, with cards placed edge to edge
+    Black,
+    /// Repeat the edge pixels of each card outwards
+    Extend,
+    /// Reflect each card across its edges
+    Mirror,
+}
+
+/// Style of guides drawn to help with cutting out cards
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CutGuides {
+    None,
+    /// Short marks at the corners of each card
+    CropMarks,
+    /// Dashed lines across the whole page along each card edge
+    CutLines,
+    /// Hairline outline around each card
+    Outlines,
+    /// Registration marks for Cricut / Silhouette style cutting machines
+    RegistrationMarks,
+}
+
+impl CutGuides {
+    /// Horizontal and vertical margin needed around the grid, in points
+    fn margin(self) -> (f32, f32) {
+        match self {
+            CutGuides::RegistrationMarks => {
+                let gap = REGISTRATION_GAP_MM * POINTS_PER_MM;
+                let size = REGISTRATION_SIZE_MM * POINTS_PER_MM;
+                let width = REGISTRATION_WIDTH_MM * POINTS_PER_MM;
+                ((gap + size).max(LINE_LEN), (gap + width).max(LINE_LEN))
+            }
+            _ => (LINE_LEN, LINE_LEN),
+        }
+    }
+}
+
+/// How the transparent rounded corners of card images are filled
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CornerMode {
+    /// Keep corners transparent, showing the bleed behind the card
+    Transparent,
+    /// Fill corners with the detected color of the card's border
+    BorderColor,
+    /// Fill corners with the nearest opaque pixel, squaring them off
+    Square,
+}
+
+/// How card images are printed
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderMode {
+    /// Full color
+    Color,
+    /// Shades of gray
+    Grayscale,
+    /// Black and white only, for high contrast playtest proxies
+    LineArt,
+}
+
+/// Filter used to resample card images to the output resolution
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResampleFilter {
+    /// Sharpest, and slowest
+    Lanczos3,
+    /// Nearly as sharp as Lanczos3, and faster
+    CatmullRom,
+    /// Keeps hard pixel edges, e.g. for pixel art
+    Nearest,
+    /// Images keep their original resolution and are scaled by the pdf viewer
+    Native,
+}
+
+impl ResampleFilter {
+    fn filter_type(self) -> Option<FilterType> {
+        match self {
+            ResampleFilter::Lanczos3 => Some(FilterType::Lanczos3),
+            ResampleFilter::CatmullRom => Some(FilterType::CatmullRom),
+            ResampleFilter::Nearest => Some(FilterType::Nearest),
+            ResampleFilter::Native => None,
+        }
+    }
+}
+
+impl FromStr for ResampleFilter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "lanczos3" => Ok(ResampleFilter::Lanczos3),
+            "catmull-rom" | "catmullrom" => Ok(ResampleFilter::CatmullRom),
+            "nearest" => Ok(ResampleFilter::Nearest),
+            "none" | "native" => Ok(ResampleFilter::Native),
+            _ => bail!("unknown resample filter {s}"),
+        }
+    }
+}
+
+/// How card images with a different aspect ratio than their card are fitted
+/// to it
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FitMode {
+    /// Scale the width and height separately, distorting the image
+    Stretch,
+    /// Scale the whole image to fit inside the card, extending it with its
+    /// border color
+    Fit,
+    /// Scale the image to cover the card, cropping the longer side equally
+    /// from both ends
+    Fill,
+}
+
+impl FromStr for FitMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "stretch" => Ok(FitMode::Stretch),
+            "fit" => Ok(FitMode::Fit),
+            "fill" => Ok(FitMode::Fill),
+            _ => bail!("unknown fit mode {s}"),
+        }
+    }
+}
+
+/// Where the stamp is placed on each card
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StampPosition {
+    /// Across the middle of the card
+    Center,
+    /// Over the rules text box
+    TextBox,
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl StampPosition {
+    /// Center of the stamp and the size of the box it is fitted in, as
+    /// fractions of the card width and height from its bottom left corner
+    fn frame(self) -> ((f32, f32), (f32, f32)) {
+        let corner = STAMP_CORNER_SIZE;
+        let (left, right) = (
+            corner.0 / 2.0 + STAMP_INSET,
+            1.0 - corner.0 / 2.0 - STAMP_INSET,
+        );
+        let (bottom, top) = (
+            corner.1 / 2.0 + STAMP_INSET,
+            1.0 - corner.1 / 2.0 - STAMP_INSET,
+        );
+        match self {
+            StampPosition::Center => ((0.5, 0.5), STAMP_SIZE),
+            StampPosition::TextBox => ((0.5, TEXT_BOX_CENTER), STAMP_SIZE),
+            StampPosition::TopLeft => ((left, top), corner),
+            StampPosition::TopRight => ((right, top), corner),
+            StampPosition::BottomLeft => ((left, bottom), corner),
+            StampPosition::BottomRight => ((right, bottom), corner),
+        }
+    }
+}
+
+impl FromStr for StampPosition {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "center" => Ok(StampPosition::Center),
+            "text-box" | "textbox" => Ok(StampPosition::TextBox),
+            "top-left" => Ok(StampPosition::TopLeft),
+            "top-right" => Ok(StampPosition::TopRight),
+            "bottom-left" => Ok(StampPosition::BottomLeft),
+            "bottom-right" => Ok(StampPosition::BottomRight),
+            _ => bail!("unknown stamp position {s}"),
+        }
+    }
+}
+
+/// Text or logo stamped on the front of every card
+enum Stamp {
+    Text(String),
+    Logo(DynamicImage),
+}
+
+/// Physical size of a card
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CardSize {
+    /// Regular 63x88mm cards
+    Standard,
+    /// Half size 44x63mm tokens
+    Token,
+    /// Mini 32x44mm reference cards
+    Mini,
+    /// Oversized 108x152mm cards, e.g. Archenemy schemes
+    Oversized,
+    /// Oversized Planechase planes, printed in landscape at 152x108mm
+    Plane,
+}
+
+impl CardSize {
+    /// Width and height in millimetres
+    pub fn dimensions(self) -> (f32, f32) {
+        match self {
+            CardSize::Standard => (CARD_WIDTH_MM, CARD_HEIGHT_MM),
+            CardSize::Token => (44.0, 63.0),
+            CardSize::Mini => (32.0, 44.0),
+            CardSize::Oversized => (108.0, 152.4),
+            CardSize::Plane => (152.4, 108.0),
+        }
+    }
+
+    fn is_landscape(self) -> bool {
+        let (width, height) = self.dimensions();
+        width > height
+    }
+
+    /// Size of card images in pixels at `dpi`, at the same resolution as
+    /// standard cards
+    fn pixels(self, dpi: u32) -> (u32, u32) {
+        let (width, height) = self.dimensions();
+        let scale = dpi as f32 / DEFAULT_DPI as f32;
+        (
+            (width / CARD_WIDTH_MM * WIDTH as f32 * scale).round() as u32,
+            (height / CARD_HEIGHT_MM * HEIGHT as f32 * scale).round() as u32,
+        )
+    }
 }
 
 #[wasm_bindgen]
 pub struct PrintJob {
     cards: Vec<Card>,
+    card_back: Option<Arc<[u8]>>,
+    page: PageSetup,
+    scale: f32,
+    bleed_mode: BleedMode,
+    // Width of bleed in millimetres
+    bleed: f32,
+    // Space between cards in millimetres
+    gutter: f32,
+    cut_guides: CutGuides,
+    corner_mode: CornerMode,
+    duplex: Duplex,
+    // Offset of back pages in points
+    back_offset: (f32, f32),
     callback: Option<Function>,
+    progress_callback: Option<Function>,
+    progress_handler: Option<Box<dyn FnMut(Progress)>>,
+    cancel_token: CancelToken,
+    skip_bad_cards: bool,
+    adjustments: Adjustments,
+    color: ColorOutput,
+    render_mode: RenderMode,
+    thin_border: bool,
+    // Errors of cards replaced by a placeholder in the last run
+    skipped: Vec<PrintError>,
+    title: String,
+    // Seconds since the Unix epoch, or the time of printing if unset
+    creation_date: Option<i64>,
+    footer: bool,
+    stamp: Option<Stamp>,
+    stamp_position: StampPosition,
+    // From 0 (invisible) to 1 (opaque)
+    stamp_opacity: f32,
+    // Counterclockwise, in degrees
+    stamp_rotation: f32,
+    output: OutputFormat,
+    // Resolution of card images and page sheets in dots per inch
+    dpi: u32,
+    resample_filter: ResampleFilter,
+    fit_mode: FitMode,
+    auto_crop: bool,
 }
 
 #[wasm_bindgen]
@@ -31,250 +466,2506 @@ impl PrintJob {
     pub fn new() -> Self {
         PrintJob {
             cards: Vec::new(),
+            card_back: None,
+            page: PageSetup::default(),
+            scale: 1.0,
+            bleed_mode: BleedMode::Black,
+            bleed: BLACK_BLEED_MM,
+            gutter: 0.0,
+            cut_guides: CutGuides::CropMarks,
+            corner_mode: CornerMode::Transparent,
+            duplex: Duplex::LongEdge,
+            back_offset: (0.0, 0.0),
             callback: None,
+            progress_callback: None,
+            progress_handler: None,
+            cancel_token: CancelToken::new(),
+            skip_bad_cards: false,
+            adjustments: Adjustments::default(),
+            color: ColorOutput::DeviceRgb,
+            render_mode: RenderMode::Color,
+            thin_border: false,
+            skipped: Vec::new(),
+            title: DEFAULT_TITLE.to_string(),
+            creation_date: None,
+            footer: false,
+            stamp: None,
+            stamp_position: StampPosition::Center,
+            stamp_opacity: DEFAULT_STAMP_OPACITY,
+            stamp_rotation: 0.0,
+            output: OutputFormat::Pdf,
+            dpi: DEFAULT_DPI,
+            resample_filter: ResampleFilter::CatmullRom,
+            fit_mode: FitMode::Stretch,
+            auto_crop: false,
         }
     }
 
     #[wasm_bindgen]
     pub fn add_card(&mut self, count: u32, data: Box<[u8]>) {
-        let data = Arc::from(data);
-        let card = Card { data, count };
+        let card = Card {
+            data: CardFace::Image(Arc::from(data)),
+            back: None,
+            count,
+            adjustments: None,
+            size: CardSize::Standard,
+            name: None,
+        };
+        self.cards.push(card);
+    }
+
+    /// Add a card with its own back face (e.g. the back of a double faced card)
+    #[wasm_bindgen]
+    pub fn add_double_sided_card(&mut self, count: u32, front: Box<[u8]>, back: Box<[u8]>) {
+        let card = Card {
+            data: CardFace::Image(Arc::from(front)),
+            back: Some(CardFace::Image(Arc::from(back))),
+            count,
+            adjustments: None,
+            size: CardSize::Standard,
+            name: None,
+        };
+        self.cards.push(card);
+    }
+
+    /// Add a text-only proxy, drawn from card data instead of an image
+    #[wasm_bindgen]
+    pub fn add_text_card(&mut self, count: u32, card: &TextCard) {
+        let card = Card {
+            data: CardFace::Text(Arc::new(card.clone())),
+            back: None,
+            count,
+            adjustments: None,
+            size: CardSize::Standard,
+            name: Some(card.name.clone()),
+        };
         self.cards.push(card);
     }
 
+    /// Add a text-only proxy of a card with two faces
+    #[wasm_bindgen]
+    pub fn add_double_sided_text_card(&mut self, count: u32, front: &TextCard, back: &TextCard) {
+        let card = Card {
+            data: CardFace::Text(Arc::new(front.clone())),
+            back: Some(CardFace::Text(Arc::new(back.clone()))),
+            count,
+            adjustments: None,
+            size: CardSize::Standard,
+            name: Some(front.name.clone()),
+        };
+        self.cards.push(card);
+    }
+
+    /// Back printed behind every card that doesn't have its own back face
+    #[wasm_bindgen]
+    pub fn set_card_back(&mut self, data: Box<[u8]>) {
+        self.card_back = Some(Arc::from(data));
+    }
+
+    #[wasm_bindgen]
+    pub fn set_cut_guides(&mut self, cut_guides: CutGuides) {
+        self.cut_guides = cut_guides;
+    }
+
+    #[wasm_bindgen]
+    pub fn set_corner_mode(&mut self, corner_mode: CornerMode) {
+        self.corner_mode = corner_mode;
+    }
+
+    #[wasm_bindgen]
+    pub fn set_duplex(&mut self, duplex: Duplex) {
+        self.duplex = duplex;
+    }
+
+    /// Shift back pages by the given offset in millimetres, to compensate for
+    /// printers that don't align both sides of the paper exactly
+    #[wasm_bindgen]
+    pub fn set_back_offset(&mut self, x: f32, y: f32) {
+        self.back_offset = (x * POINTS_PER_MM, y * POINTS_PER_MM);
+    }
+
+    /// Callback called with a progress message after each step
     #[wasm_bindgen]
     pub fn add_callback(&mut self, callback: Function) {
         self.callback = Some(callback)
     }
 
-    #[wasm_bindgen]
-    pub fn run(&mut self) -> Result<Vec<u8>, String> {
-        self.print().map_err(|err| err.to_string())
+    /// Callback called with a `Progress` object after each step
+    #[wasm_bindgen]
+    pub fn add_progress_callback(&mut self, callback: Function) {
+        self.progress_callback = Some(callback)
+    }
+
+    /// Handle that cancels the job when `cancel` is called on it, e.g. from a
+    /// progress callback
+    #[wasm_bindgen]
+    pub fn cancel_token(&self) -> CancelToken {
+        self.cancel_token.clone()
+    }
+
+    /// Use an existing token to cancel the job, so that several jobs can be
+    /// cancelled together
+    #[wasm_bindgen]
+    pub fn set_cancel_token(&mut self, token: &CancelToken) {
+        self.cancel_token = token.clone();
+    }
+
+    #[wasm_bindgen]
+    pub fn set_paper_size(&mut self, paper: PaperSize) {
+        self.page = PageSetup::new(paper, self.page.orientation);
+    }
+
+    #[wasm_bindgen]
+    pub fn set_custom_paper_size(&mut self, width: f32, height: f32) -> Result<(), String> {
+        self.page = PageSetup::custom(width, height, self.page.orientation)
+            .map_err(|err| err.to_string())?;
+        Ok(())
+    }
+
+    #[wasm_bindgen]
+    pub fn set_orientation(&mut self, orientation: Orientation) {
+        self.page.orientation = orientation;
+    }
+
+    /// Scale correction applied to the physical card size, to compensate for
+    /// printers that don't print at exactly 100% (e.g. 0.995)
+    #[wasm_bindgen]
+    pub fn set_scale(&mut self, scale: f32) -> Result<(), String> {
+        if !(scale.is_finite() && scale > 0.0) {
+            return Err(format!("invalid scale {scale}"));
+        }
+        self.scale = scale;
+        Ok(())
+    }
+
+    /// Set how bleed is generated around each card, and its width in millimetres.
+    /// Cards with generated bleed are spaced apart so each can be cut individually
+    #[wasm_bindgen]
+    pub fn set_bleed(&mut self, mode: BleedMode, width: f32) -> Result<(), String> {
+        if !(0.0..=MAX_BLEED_MM).contains(&width) {
+            return Err(format!("invalid bleed width {width}mm"));
+        }
+        self.bleed_mode = mode;
+        self.bleed = width;
+        Ok(())
+    }
+
+    /// Set the space between neighbouring cards in millimetres, so each card
+    /// can be trimmed without cutting into the next
+    #[wasm_bindgen]
+    pub fn set_gutter(&mut self, width: f32) -> Result<(), String> {
+        if !(0.0..=MAX_GUTTER_MM).contains(&width) {
+            return Err(format!("invalid gutter width {width}mm"));
+        }
+        self.gutter = width;
+        Ok(())
+    }
+
+    /// Use the standard print shop layout of 63x88mm cards with 3mm of bleed on
+    /// each side (69x94mm in total)
+    #[wasm_bindgen]
+    pub fn set_print_shop_bleed(&mut self) {
+        self.bleed_mode = BleedMode::Extend;
+        self.bleed = PRINT_SHOP_BLEED_MM;
+    }
+
+    /// Number of standard size cards that fit on each page with the current
+    /// settings
+    #[wasm_bindgen]
+    pub fn cards_per_page(&self) -> Result<u32, String> {
+        let grid = self
+            .grid(CardSize::Standard)
+            .map_err(|err| err.to_string())?;
+        Ok(grid.count() as u32)
+    }
+
+    /// Adjust the colors and sharpness of every card image
+    #[wasm_bindgen]
+    pub fn set_adjustments(&mut self, adjustments: &Adjustments) -> Result<(), String> {
+        adjustments.validate().map_err(|err| err.to_string())?;
+        self.adjustments = *adjustments;
+        Ok(())
+    }
+
+    /// Adjust the images of a single card instead of using the adjustments of
+    /// the job. Cards are numbered from 0 in the order they were added
+    #[wasm_bindgen]
+    pub fn set_card_adjustments(
+        &mut self,
+        card: u32,
+        adjustments: &Adjustments,
+    ) -> Result<(), String> {
+        adjustments.validate().map_err(|err| err.to_string())?;
+        let card = self
+            .cards
+            .get_mut(card as usize)
+            .ok_or_else(|| format!("invalid card index {card}"))?;
+        card.adjustments = Some(*adjustments);
+        Ok(())
+    }
+
+    /// Set the physical size of a single card, e.g. for tokens or Planechase
+    /// planes. Cards are numbered from 0 in the order they were added
+    #[wasm_bindgen]
+    pub fn set_card_size(&mut self, card: u32, size: CardSize) -> Result<(), String> {
+        let card = self
+            .cards
+            .get_mut(card as usize)
+            .ok_or_else(|| format!("invalid card index {card}"))?;
+        card.size = size;
+        Ok(())
+    }
+
+    /// Set the name of a single card, listed in the outline and footer of the
+    /// pages it is printed on. Text cards are named after their front face
+    #[wasm_bindgen]
+    pub fn set_card_name(&mut self, card: u32, name: String) -> Result<(), String> {
+        let card = self
+            .cards
+            .get_mut(card as usize)
+            .ok_or_else(|| format!("invalid card index {card}"))?;
+        card.name = Some(name);
+        Ok(())
+    }
+
+    /// Title of the pdf shown by viewers
+    #[wasm_bindgen]
+    pub fn set_title(&mut self, title: String) {
+        self.title = title;
+    }
+
+    /// Creation date of the pdf in seconds since the Unix epoch, for
+    /// reproducible output. Defaults to the time the pdf is printed
+    #[wasm_bindgen]
+    pub fn set_creation_date(&mut self, timestamp: f64) {
+        self.creation_date = Some(timestamp as i64);
+    }
+
+    /// Print the page number and the names of the cards on each page below
+    /// the cards. Only pdfs have a footer, and only on pages with room for it
+    /// inside the margin printers can print in
+    #[wasm_bindgen]
+    pub fn set_footer(&mut self, footer: bool) {
+        self.footer = footer;
+    }
+
+    /// Stamp `text` on the front of every card, e.g. "PROXY", so proxies are
+    /// visibly marked. An empty text removes the stamp. Image and svg outputs
+    /// have the stamp drawn into the pixels of each card
+    #[wasm_bindgen]
+    pub fn set_stamp_text(&mut self, text: String) {
+        self.stamp = (!text.is_empty()).then_some(Stamp::Text(text));
+    }
+
+    /// Stamp a logo image on the front of every card instead of text
+    #[wasm_bindgen]
+    pub fn set_stamp_logo(&mut self, data: Box<[u8]>) -> Result<(), String> {
+        let logo = decode_image(&data, None).map_err(|err| match err {
+            PrintError::Decode { message, .. } => format!("invalid stamp logo: {message}"),
+            PrintError::OversizedImage { width, height, .. } => {
+                format!("stamp logo is too large ({width}x{height})")
+            }
+            _ => "unsupported stamp logo format".to_string(),
+        })?;
+        self.stamp = Some(Stamp::Logo(logo));
+        Ok(())
+    }
+
+    /// Set where the stamp is placed on each card, its opacity from 0 to 1,
+    /// and its counterclockwise rotation in degrees
+    #[wasm_bindgen]
+    pub fn set_stamp_style(
+        &mut self,
+        position: StampPosition,
+        opacity: f32,
+        rotation: f32,
+    ) -> Result<(), String> {
+        if !(0.0..=1.0).contains(&opacity) {
+            return Err(format!("invalid stamp opacity {opacity}"));
+        }
+        if !rotation.is_finite() {
+            return Err(format!("invalid stamp rotation {rotation}"));
+        }
+        self.stamp_position = position;
+        self.stamp_opacity = opacity;
+        self.stamp_rotation = rotation;
+        Ok(())
+    }
+
+    /// Output a zip of card images or page sheets instead of a pdf
+    #[wasm_bindgen]
+    pub fn set_output_format(&mut self, format: OutputFormat) {
+        self.output = format;
+    }
+
+    /// Resolution card images are resampled to, and of page sheets, in dots
+    /// per inch. Lower resolutions make smaller pdfs, e.g. 150 for playtesting,
+    /// and higher ones sharper prints, e.g. 600 for high resolution scans
+    #[wasm_bindgen]
+    pub fn set_dpi(&mut self, dpi: u32) -> Result<(), String> {
+        if !(MIN_DPI..=MAX_DPI).contains(&dpi) {
+            return Err(format!("dpi must be between {MIN_DPI} and {MAX_DPI}"));
+        }
+        self.dpi = dpi;
+        Ok(())
+    }
+
+    /// Set the filter card images are resampled with. `ResampleFilter::Native`
+    /// skips resampling, so the pdf viewer scales the original images
+    #[wasm_bindgen]
+    pub fn set_resample_filter(&mut self, filter: ResampleFilter) {
+        self.resample_filter = filter;
+    }
+
+    /// Set how card images with a different aspect ratio than their card are
+    /// fitted to it. Images are stretched by default
+    #[wasm_bindgen]
+    pub fn set_fit_mode(&mut self, fit_mode: FitMode) {
+        self.fit_mode = fit_mode;
+    }
+
+    /// Detect the card in scanned or photographed card images, then straighten
+    /// them and crop them to it. Images that already have the aspect ratio of
+    /// a card, or have no clear card outline, are left as is
+    #[wasm_bindgen]
+    pub fn set_auto_crop(&mut self, auto_crop: bool) {
+        self.auto_crop = auto_crop;
+    }
+
+    /// Set the color space of card images. Cmyk output requires an ICC profile
+    /// for the printer, which is used to convert images and embedded in the pdf
+    #[wasm_bindgen]
+    pub fn set_color_mode(
+        &mut self,
+        mode: ColorMode,
+        profile: Option<Box<[u8]>>,
+    ) -> Result<(), String> {
+        self.color = ColorOutput::new(mode, profile).map_err(|err| err.to_string())?;
+        Ok(())
+    }
+
+    #[wasm_bindgen]
+    pub fn set_render_mode(&mut self, render_mode: RenderMode) {
+        self.render_mode = render_mode;
+    }
+
+    /// Replace the frame of each card with white, leaving a thin outline, to
+    /// save ink. Black bleed is not drawn, since it would no longer match
+    #[wasm_bindgen]
+    pub fn set_thin_border(&mut self, thin_border: bool) {
+        self.thin_border = thin_border;
+    }
+
+    /// Replace cards whose image can't be used with a placeholder, instead of
+    /// failing the whole job
+    #[wasm_bindgen]
+    pub fn set_skip_bad_cards(&mut self, skip_bad_cards: bool) {
+        self.skip_bad_cards = skip_bad_cards;
+    }
+
+    /// Indices of cards that were replaced by a placeholder in the last run
+    #[wasm_bindgen]
+    pub fn skipped_cards(&self) -> Vec<u32> {
+        self.skipped
+            .iter()
+            .filter_map(|err| err.card())
+            .map(|card| card as u32)
+            .collect()
+    }
+
+    /// Generate the pdf. Errors are thrown as javascript `Error`s with `kind`
+    /// and `card` properties
+    #[wasm_bindgen]
+    pub fn run(&mut self) -> Result<Vec<u8>, PrintError> {
+        self.print()
+    }
+}
+
+// Largest width or height of a page in points, which pdf viewers limit pages
+// to (200 inches)
+const MAX_PAGE_SIZE: f32 = 14400.0;
+// Cut line options, in points
+const LINE_LEN: f32 = 9.6;
+const LINE_WIDTH: f32 = 0.5;
+const LINE_GRAY: f32 = 0.5;
+const DASH_LEN: f32 = 4.0;
+// Registration mark options, in millimetres
+const REGISTRATION_SIZE_MM: f32 = 5.0;
+const REGISTRATION_LEN_MM: f32 = 20.0;
+const REGISTRATION_WIDTH_MM: f32 = 0.5;
+const REGISTRATION_GAP_MM: f32 = 2.0;
+// Distance inside the edge of a card where its border color is sampled, as
+// thousandths of the card width
+const BORDER_INSET: u32 = 15;
+// Width of the frame replaced by thin border mode, and of the outline left
+// behind, as thousandths of the card width
+const FRAME_WIDTH: u32 = 45;
+const OUTLINE_WIDTH: u32 = 4;
+// Largest difference in each channel from the border color for a pixel to be
+// part of the frame
+const FRAME_TOLERANCE: u8 = 40;
+// Gray level at and above which line art pixels are white
+const LINE_ART_THRESHOLD: u8 = 128;
+// Bleed options, in millimetres
+const BLACK_BLEED_MM: f32 = 0.7;
+const PRINT_SHOP_BLEED_MM: f32 = 3.0;
+const MAX_BLEED_MM: f32 = 10.0;
+const MAX_GUTTER_MM: f32 = 10.0;
+// Width of a card image in pixels, at the default resolution
+const WIDTH: u32 = 745;
+const HEIGHT: u32 = 1040;
+// Resolution of card images and page sheets, in dots per inch
+const DEFAULT_DPI: u32 = 300;
+const MIN_DPI: u32 = 72;
+const MAX_DPI: u32 = 1200;
+// Physical size of a card in millimetres
+const CARD_WIDTH_MM: f32 = 63.0;
+const CARD_HEIGHT_MM: f32 = 88.0;
+const POINTS_PER_MM: f32 = 72.0 / 25.4;
+// Largest width or height of a card image that will be decoded, in pixels
+const MAX_IMAGE_SIZE: u32 = 10000;
+
+// Layout of text proxies in points, at full scale
+const PROXY_MARGIN: f32 = 9.0;
+const PROXY_PADDING: f32 = 4.0;
+const PROXY_BAR_HEIGHT: f32 = 16.0;
+const PROXY_STATS_WIDTH: f32 = 40.0;
+const PROXY_FRAME_WIDTH: f32 = 1.0;
+const PROXY_RULE_WIDTH: f32 = 0.5;
+// Outline width of bold text
+const PROXY_BOLD_WIDTH: f32 = 0.3;
+const PROXY_TITLE_SIZE: f32 = 9.5;
+const PROXY_TYPE_SIZE: f32 = 8.0;
+const PROXY_TEXT_SIZE: f32 = 8.5;
+const PROXY_MIN_TEXT_SIZE: f32 = 5.0;
+const PROXY_TEXT_STEP: f32 = 0.25;
+// Line and paragraph spacing, relative to the font size
+const PROXY_LEADING: f32 = 1.2;
+const PROXY_PARAGRAPH_GAP: f32 = 0.4;
+// Footer options, in points. The footer is kept a quarter inch from the
+// edge of the page, which most printers can't print closer to
+const FOOTER_SIZE: f32 = 6.0;
+const FOOTER_GRAY: f32 = 0.4;
+const FOOTER_MARGIN: f32 = 18.0;
+// Stamp options. Sizes are the box the stamp is fitted in, and the inset of
+// corner stamps from the card edges, as fractions of the card size
+const STAMP_SIZE: (f32, f32) = (0.7, 0.2);
+const STAMP_CORNER_SIZE: (f32, f32) = (0.3, 0.08);
+const STAMP_INSET: f32 = 0.06;
+const STAMP_GRAY: f32 = 0.1;
+const DEFAULT_STAMP_OPACITY: f32 = 0.5;
+// Center of the rules text box above the bottom of a card, as a fraction of
+// its height
+const TEXT_BOX_CENTER: f32 = 0.24;
+// Name of the stamp logo and its transparency in page resources
+const STAMP_NAME: &str = "Stamp";
+// Document metadata
+const DEFAULT_TITLE: &str = "MTG Proxies";
+const CREATOR: &str = "mtg-print";
+// Placeholder for cards that can't be printed
+const PLACEHOLDER_BACKGROUND: Rgb<u8> = Rgb([0xe0, 0xe0, 0xe0]);
+const PLACEHOLDER_FOREGROUND: Rgb<u8> = Rgb([0x60, 0x60, 0x60]);
+const PLACEHOLDER_LINE_WIDTH: f32 = 12.0;
+
+/// Physical size of a card in points, adjusted by `scale`
+fn card_size(size: CardSize, scale: f32) -> (f32, f32) {
+    let (width, height) = size.dimensions();
+    (
+        width * POINTS_PER_MM * scale,
+        height * POINTS_PER_MM * scale,
+    )
+}
+
+/// Layout of cards on a page, in points
+#[derive(Debug, Clone, Copy)]
+struct Grid {
+    rows: u32,
+    cols: u32,
+    page_width: f32,
+    page_height: f32,
+    card_width: f32,
+    card_height: f32,
+    // Generated bleed around each card
+    bleed: f32,
+    // Space between neighbouring cards, outside their bleed
+    gutter: f32,
+    // Top left corner of the grid
+    left: f32,
+    top: f32,
+}
+
+impl Grid {
+    /// Fit as many cards of `size` (and their cut lines) as possible on the
+    /// page, with `gutter` points between them
+    fn fit(
+        page: &PageSetup,
+        size: CardSize,
+        scale: f32,
+        bleed_pixels: u32,
+        gutter: f32,
+        guides: CutGuides,
+    ) -> Result<Self> {
+        let (page_width, page_height) = page.dimensions();
+        let (card_width, card_height) = card_size(size, scale);
+        // Bleed is the same width for every card size
+        let (standard_width, _) = card_size(CardSize::Standard, scale);
+        let bleed = bleed_pixels as f32 * standard_width / WIDTH as f32;
+        let (cell_width, cell_height) = (card_width + 2.0 * bleed, card_height + 2.0 * bleed);
+        let (margin_x, margin_y) = guides.margin();
+        // Every card but the last in each row and column is followed by a gutter
+        let cols = ((page_width - 2.0 * margin_x + gutter) / (cell_width + gutter))
+            .floor()
+            .max(0.0) as u32;
+        let rows = ((page_height - 2.0 * margin_y + gutter) / (cell_height + gutter))
+            .floor()
+            .max(0.0) as u32;
+        if rows == 0 || cols == 0 {
+            bail!("page is too small to fit a card");
+        }
+        let grid = Grid {
+            rows,
+            cols,
+            page_width,
+            page_height,
+            card_width,
+            card_height,
+            bleed,
+            gutter,
+            left: 0.0,
+            top: 0.0,
+        };
+        Ok(grid.with_rows(rows, (page_height + grid.height_of(rows)) / 2.0))
+    }
+
+    /// The grid cut down to `rows` rows, centered horizontally with its top
+    /// edge at `top`
+    fn with_rows(self, rows: u32, top: f32) -> Self {
+        let width = self.cols as f32 * self.pitch_x() - self.gutter;
+        Grid {
+            rows,
+            left: (self.page_width - width) / 2.0,
+            top,
+            ..self
+        }
+    }
+
+    /// Height of `rows` rows of cards, including bleed
+    fn height_of(&self, rows: u32) -> f32 {
+        rows as f32 * self.pitch_y() - self.gutter
+    }
+
+    fn count(&self) -> usize {
+        (self.rows * self.cols) as usize
+    }
+
+    /// Bottom left corner of the card at index `idx`
+    fn position(&self, idx: usize) -> (f32, f32) {
+        let i = idx as u32 % self.cols;
+        let j = idx as u32 / self.cols;
+        let x = self.left + i as f32 * self.pitch_x() + self.bleed;
+        let y = self.top - j as f32 * self.pitch_y() - self.cell_height() + self.bleed;
+        (x, y)
+    }
+
+    /// Horizontal positions of card edges, from left to right
+    fn trim_xs(&self) -> Vec<f32> {
+        let mut xs = Vec::new();
+        for i in 0..self.cols {
+            let x = self.left + i as f32 * self.pitch_x() + self.bleed;
+            xs.extend([x, x + self.card_width]);
+        }
+        xs.dedup_by(|a, b| (*a - *b).abs() < 0.01);
+        xs
+    }
+
+    /// Vertical positions of card edges, from top to bottom
+    fn trim_ys(&self) -> Vec<f32> {
+        let mut ys = Vec::new();
+        for j in 0..self.rows {
+            let y = self.top - j as f32 * self.pitch_y() - self.bleed;
+            ys.extend([y, y - self.card_height]);
+        }
+        ys.dedup_by(|a, b| (*a - *b).abs() < 0.01);
+        ys
+    }
+
+    /// Size of a card including its bleed
+    fn cell_width(&self) -> f32 {
+        self.card_width + 2.0 * self.bleed
+    }
+
+    fn cell_height(&self) -> f32 {
+        self.card_height + 2.0 * self.bleed
+    }
+
+    /// Distance between the same edge of neighbouring cards
+    fn pitch_x(&self) -> f32 {
+        self.cell_width() + self.gutter
+    }
+
+    fn pitch_y(&self) -> f32 {
+        self.cell_height() + self.gutter
+    }
+
+    /// Right edge of the grid, including bleed
+    fn right(&self) -> f32 {
+        self.left + self.cols as f32 * self.pitch_x() - self.gutter
+    }
+
+    /// Bottom edge of the grid, including bleed
+    fn bottom(&self) -> f32 {
+        self.top - self.height_of(self.rows)
+    }
+
+    /// Bottom left corner of the back of the card at index `idx`, such that it
+    /// lines up with the front once the paper is flipped
+    fn back_position(&self, idx: usize, mirror: MirrorAxis, offset: (f32, f32)) -> (f32, f32) {
+        let (x, y) = self.position(idx);
+        let (x, y) = match mirror {
+            MirrorAxis::Horizontal => (self.page_width - x - self.card_width, y),
+            MirrorAxis::Vertical => (x, self.page_height - y - self.card_height),
+        };
+        (x + offset.0, y + offset.1)
+    }
+}
+
+/// Axis that back pages are mirrored across
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MirrorAxis {
+    Horizontal,
+    Vertical,
+}
+
+impl MirrorAxis {
+    fn new(page: &PageSetup, duplex: Duplex) -> Self {
+        let (width, height) = page.dimensions();
+        let long_edge_vertical = height >= width;
+        match (duplex, long_edge_vertical) {
+            (Duplex::LongEdge, true) | (Duplex::ShortEdge, false) => MirrorAxis::Horizontal,
+            (Duplex::LongEdge, false) | (Duplex::ShortEdge, true) => MirrorAxis::Vertical,
+        }
+    }
+}
+
+/// Images of the front and (optional) back face of a card slot
+#[derive(Debug, Clone, Copy)]
+struct Slot {
+    // Index of the card in the job
+    card: usize,
+    front: usize,
+    back: Option<usize>,
+    size: CardSize,
+}
+
+/// Cards of the same size laid out in a grid on part of a page
+struct Section {
+    grid: Grid,
+    slots: Vec<Slot>,
+}
+
+/// Shape drawn on a page, in points from the bottom left of the page. Pdf
+/// pages and exported sheets are drawn from the same shapes
+enum Shape {
+    /// Rectangles `[x, y, width, height]` filled with a gray level
+    Fill { gray: f32, rects: Vec<[f32; 4]> },
+    /// Lines through each path of points, and outlines of rectangles, stroked
+    /// with a gray level, width and dash length. A width of 0 is the
+    /// thinnest line the output device can draw
+    Stroke {
+        gray: f32,
+        width: f32,
+        dash: Option<f32>,
+        paths: Vec<Vec<(f32, f32)>>,
+        rects: Vec<[f32; 4]>,
+    },
+    /// Card image mapped to the unit square by the matrix `[a, d, e, f]`,
+    /// where a negative scale means it is rotated 180 degrees
+    Image { idx: usize, matrix: [f32; 4] },
+}
+
+/// Card face, the index of the card it belongs to, and the adjustments applied
+/// to its image
+struct CardImage {
+    face: CardFace,
+    card: Option<usize>,
+    // Whether this is the back of its card, or the shared card back
+    back: bool,
+    adjustments: Adjustments,
+    size: CardSize,
+}
+
+/// Settings for converting card images, shared between threads when images
+/// are converted in parallel
+#[derive(Clone)]
+struct ImageOptions {
+    // Width of generated bleed in millimetres
+    bleed: f32,
+    bleed_mode: BleedMode,
+    corner_mode: CornerMode,
+    skip_bad_cards: bool,
+    color: ColorOutput,
+    render_mode: RenderMode,
+    thin_border: bool,
+    dpi: u32,
+    filter: ResampleFilter,
+    fit_mode: FitMode,
+    auto_crop: bool,
+}
+
+/// Card image converted to pdf streams, ready to be written
+struct EncodedImage {
+    image: Stream,
+    mask: Option<Stream>,
+}
+
+/// Card face ready to be written. Text proxies are laid out while writing,
+/// since they share the embedded font
+enum EncodedFace {
+    Image(Box<EncodedImage>),
+    Text(Arc<TextCard>),
+}
+
+/// Encoded card face, and the error of a card replaced by a placeholder
+type EncodeResult = Result<(EncodedFace, Option<PrintError>), PrintError>;
+
+impl ImageOptions {
+    /// Convert a card image to an image XObject, replacing it with a
+    /// placeholder if it can't be used and bad cards are skipped. The error of
+    /// a skipped card is returned alongside the placeholder
+    fn encode_card(&self, image: &CardImage) -> EncodeResult {
+        let CardImage {
+            face,
+            card,
+            adjustments,
+            size,
+            ..
+        } = image;
+        let data = match face {
+            CardFace::Image(data) => data,
+            CardFace::Text(text) => return Ok((EncodedFace::Text(text.clone()), None)),
+        };
+        match self.encode(data, *card, adjustments, *size) {
+            Ok(encoded) => Ok((EncodedFace::Image(Box::new(encoded)), None)),
+            Err(err) if self.skip_bad_cards && err.is_card_error() => {
+                let encoded = self.encode_decoded(placeholder(), *size)?;
+                Ok((EncodedFace::Image(Box::new(encoded)), Some(err)))
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    fn encode(
+        &self,
+        data: &[u8],
+        card: Option<usize>,
+        adjustments: &Adjustments,
+        size: CardSize,
+    ) -> Result<EncodedImage, PrintError> {
+        // Jpeg images are embedded as is, and scaled by the pdf viewer, when
+        // they already have the output resolution or are kept at theirs
+        let passthrough = self.bleed == 0.0
+            && adjustments.is_identity()
+            && self.color.is_rgb()
+            && self.render_mode == RenderMode::Color
+            && !self.thin_border
+            && !self.auto_crop
+            && self.fit_mode == FitMode::Stretch;
+        // Images in the wrong orientation need to be rotated
+        let oriented = |header: &JpegHeader| (header.width > header.height) == size.is_landscape();
+        let resolution = |header: &JpegHeader| {
+            self.filter == ResampleFilter::Native
+                || (header.width, header.height) == size.pixels(self.dpi)
+        };
+        if let Some(header) = JpegHeader::parse(data)
+            .filter(|header| passthrough && oriented(header) && resolution(header))
+        {
+            let (width, height) = (header.width, header.height);
+            if width > MAX_IMAGE_SIZE || height > MAX_IMAGE_SIZE {
+                return Err(PrintError::OversizedImage {
+                    card,
+                    width,
+                    height,
+                });
+            }
+            let dict = dictionary! {
+                "Type" => "XObject",
+                "Subtype" => "Image",
+                "Width" => width,
+                "Height" => height,
+                "ColorSpace" => header.color_space,
+                "BitsPerComponent" => 8,
+                "Filter" => "DCTDecode",
+            };
+            let image = Stream::new(dict, data.to_vec()).with_compression(false);
+            return Ok(EncodedImage { image, mask: None });
+        }
+
+        let image = adjustments.apply(decode_image(data, card)?);
+        Ok(self.encode_decoded(image, size)?)
+    }
+
+    /// Crop a decoded card image to its card if enabled, resample it to `size`
+    /// at the output resolution, and add corners and bleed. Also returns the
+    /// width of the bleed in pixels
+    fn prepare(&self, image: DynamicImage, size: CardSize) -> (DynamicImage, u32) {
+        let image = if self.auto_crop {
+            crop::auto_crop(&image).unwrap_or(image)
+        } else {
+            image
+        };
+        // Portrait scans of landscape cards (e.g. planes) are turned sideways
+        let image = if size.is_landscape() && image.height() > image.width() {
+            image.rotate90()
+        } else {
+            image
+        };
+        let (width, height) = size.dimensions();
+        let image = match self.fit_mode {
+            FitMode::Stretch => image,
+            FitMode::Fit => pad_to_aspect(image, width / height),
+            FitMode::Fill => crop_to_aspect(image, width / height),
+        };
+        let mut image = match self.filter.filter_type() {
+            Some(filter) => {
+                let (width, height) = size.pixels(self.dpi);
+                image.resize_exact(width, height, filter)
+            }
+            None => image,
+        };
+        if self.thin_border {
+            let mut rgba = image.into_rgba8();
+            thin_border(&mut rgba);
+            image = DynamicImage::from(rgba);
+        }
+        if image.color().has_alpha() && self.corner_mode != CornerMode::Transparent {
+            let mut rgba = image.into_rgba8();
+            fill_corners(&mut rgba, self.corner_mode);
+            image = DynamicImage::from(rgba);
+        }
+        // Bleed is converted to pixels of this image, whose resolution
+        // differs between cards at native resolution
+        let bleed = (self.bleed * image.width() as f32 / size.dimensions().0).round() as u32;
+        if bleed > 0 {
+            let rgba = image.into_rgba8();
+            image = DynamicImage::from(add_bleed(&rgba, bleed, self.bleed_mode));
+        }
+        (image, bleed)
+    }
+
+    /// Resample a decoded card image to `size`, add corners and bleed, and
+    /// compress it
+    fn encode_decoded(&self, image: DynamicImage, size: CardSize) -> Result<EncodedImage> {
+        self.encode_pixels(self.prepare(image, size).0)
+    }
+
+    /// Compress an image in the color and render mode of the job
+    fn encode_pixels(&self, image: DynamicImage) -> Result<EncodedImage> {
+        let (width, height) = image.dimensions();
+
+        // Transparency (e.g. rounded corners) is kept as a soft mask
+        let alpha = image.color().has_alpha().then(|| {
+            image
+                .to_rgba8()
+                .pixels()
+                .map(|&Rgba([_, _, _, a])| a)
+                .collect::<Vec<_>>()
+        });
+        let mask = match alpha.filter(|alpha| alpha.iter().any(|&a| a != 0xff)) {
+            Some(alpha) => {
+                let mask_dict = dictionary! {
+                    "Type" => "XObject",
+                    "Subtype" => "Image",
+                    "Width" => width,
+                    "Height" => height,
+                    "ColorSpace" => "DeviceGray",
+                    "BitsPerComponent" => 8,
+                };
+                let mut mask = Stream::new(mask_dict, alpha);
+                mask.compress()?;
+                Some(mask)
+            }
+            None => None,
+        };
+
+        let (pixels, color_space) = match self.render_mode {
+            RenderMode::Color => self.color.convert(image.into_rgb8().into_raw())?,
+            RenderMode::Grayscale => (image.into_luma8().into_raw(), "DeviceGray"),
+            RenderMode::LineArt => {
+                let mut gray = image.into_luma8();
+                for pixel in gray.pixels_mut() {
+                    pixel[0] = if pixel[0] >= LINE_ART_THRESHOLD {
+                        0xff
+                    } else {
+                        0x00
+                    };
+                }
+                (gray.into_raw(), "DeviceGray")
+            }
+        };
+        let dict = dictionary! {
+            "Type" => "XObject",
+            "Subtype" => "Image",
+            "Width" => width,
+            "Height" => height,
+            "ColorSpace" => color_space,
+            "BitsPerComponent" => 8,
+        };
+        let mut image = Stream::new(dict, pixels);
+        image.compress()?;
+        Ok(EncodedImage { image, mask })
+    }
+}
+
+/// Number of images converted at once
+fn encode_threads() -> usize {
+    #[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+    return rayon::current_num_threads();
+    #[cfg(not(all(feature = "parallel", not(target_arch = "wasm32"))))]
+    return 1;
+}
+
+/// Convert the card images at `indices`, in parallel on native builds with the
+/// `parallel` feature
+fn encode_images(
+    options: &ImageOptions,
+    images: &[CardImage],
+    indices: &[usize],
+) -> Vec<EncodeResult> {
+    #[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+    {
+        use rayon::prelude::*;
+        indices
+            .par_iter()
+            .map(|&idx| options.encode_card(&images[idx]))
+            .collect()
+    }
+    #[cfg(not(all(feature = "parallel", not(target_arch = "wasm32"))))]
+    {
+        indices
+            .iter()
+            .map(|&idx| options.encode_card(&images[idx]))
+            .collect()
     }
 }
 
-// Layout of cards on the page
-const ROWS: u32 = 3;
-const COLS: u32 = 3;
-const CARD_COUNT: u32 = ROWS * COLS;
-// Line options
-const LINE_LEN: u32 = 40;
-const LINE_WIDTH: u32 = 1;
-const LINE_COLOR: Rgba<u8> = Rgba([0x7f, 0x7f, 0x7f, 0xff]);
-const BLACK_BLEED: u32 = 8;
-// Width of a card image in pixels
-const WIDTH: u32 = 745;
-const HEIGHT: u32 = 1040;
-// Dimensions of letter paper
-const PAGE_WIDTH: u32 = 595;
-const PAGE_HEIGHT: u32 = 792;
+/// Write an encoded card image and its soft mask to the pdf, replacing device
+/// rgb with `rgb_color_space`
+fn write_image<W: Write>(
+    pdf: &mut PdfWriter<W>,
+    encoded: EncodedImage,
+    rgb_color_space: &Object,
+) -> Result<ObjectId> {
+    let EncodedImage { mut image, mask } = encoded;
+    if let Ok(b"DeviceRGB") = image.dict.get(b"ColorSpace").and_then(Object::as_name) {
+        image.dict.set("ColorSpace", rgb_color_space.clone());
+    }
+    if let Some(mask) = mask {
+        image.dict.set("SMask", pdf.add(mask)?);
+    }
+    pdf.add(image)
+}
 
 impl PrintJob {
-    fn report_progress(&mut self, message: &str) -> Result<()> {
-        if let Some(callback) = &mut self.callback {
+    /// Native errors of cards replaced by a placeholder in the last run
+    pub fn skipped(&self) -> &[PrintError] {
+        &self.skipped
+    }
+
+    /// Call the progress handler from native code after each step
+    pub fn on_progress(&mut self, handler: impl FnMut(Progress) + 'static) {
+        self.progress_handler = Some(Box::new(handler));
+    }
+
+    /// Report progress to every callback, and stop the job if it has been
+    /// cancelled
+    fn report_progress(&mut self, progress: Progress) -> Result<(), PrintError> {
+        if let Some(callback) = &self.callback {
+            let message = progress.to_string();
             let _ = callback.call1(&JsValue::null(), &JsValue::from(message));
         }
+        if let Some(callback) = &self.progress_callback {
+            let _ = callback.call1(&JsValue::null(), &JsValue::from(progress));
+        }
+        if let Some(handler) = &mut self.progress_handler {
+            handler(progress);
+        }
+        if self.cancel_token.is_cancelled() {
+            return Err(PrintError::Cancelled);
+        }
         Ok(())
     }
-    pub fn print(&mut self) -> Result<Vec<u8>> {
-        // Create chunks of 9 cards each
-        let mut chunks = Vec::new();
-        let mut chunk = Vec::new();
-        for card in self.cards.iter() {
-            for _ in 0..card.count {
-                chunk.push(card.data.clone());
-                if chunk.len() == 9 {
-                    let full_chunk = mem::replace(&mut chunk, Vec::new());
-                    chunks.push(full_chunk);
-                }
+
+    /// Width of generated bleed in card image pixels
+    fn bleed_pixels(&self) -> u32 {
+        match self.bleed_mode {
+            BleedMode::Black => 0,
+            BleedMode::Extend | BleedMode::Mirror => {
+                (self.bleed / CARD_WIDTH_MM * WIDTH as f32).round() as u32
             }
         }
-        if chunk.len() != 0 {
-            chunks.push(chunk);
+    }
+
+    /// Width of black bleed drawn behind each card in points
+    fn black_bleed(&self) -> f32 {
+        match self.bleed_mode {
+            BleedMode::Black if self.thin_border => 0.0,
+            BleedMode::Black => self.bleed * POINTS_PER_MM * self.scale,
+            BleedMode::Extend | BleedMode::Mirror => 0.0,
         }
+    }
 
-        // Create a page for each chunk
-        let mut pages = Vec::new();
-        let len = chunks.len();
-        for (i, chunk) in chunks.into_iter().enumerate() {
-            self.report_progress(&format!("Generating page images ({} / {})", i + 1, len))?;
-            let page = self.create_page(chunk)?;
-            pages.push(page);
+    fn image_options(&self) -> ImageOptions {
+        ImageOptions {
+            bleed: self.bleed_pixels() as f32 * CARD_WIDTH_MM / WIDTH as f32,
+            bleed_mode: self.bleed_mode,
+            corner_mode: self.corner_mode,
+            skip_bad_cards: self.skip_bad_cards,
+            color: self.color.clone(),
+            render_mode: self.render_mode,
+            thin_border: self.thin_border,
+            dpi: self.dpi,
+            filter: self.resample_filter,
+            fit_mode: self.fit_mode,
+            auto_crop: self.auto_crop,
         }
+    }
+
+    fn grid(&self, size: CardSize) -> Result<Grid> {
+        let gutter = self.gutter * POINTS_PER_MM * self.scale;
+        Grid::fit(
+            &self.page,
+            size,
+            self.scale,
+            self.bleed_pixels(),
+            gutter,
+            self.cut_guides,
+        )
+    }
 
-        // Create final pdf
-        let pdf = self.create_pdf(pages)?;
-        Ok(pdf)
+    pub fn print(&mut self) -> Result<Vec<u8>, PrintError> {
+        self.print_to(Vec::new())
     }
-    fn create_page(&self, pngs: Vec<Arc<[u8]>>) -> Result<DynamicImage> {
-        // Convert images from png
+
+    /// Write the pdf (or zip of another output format) to `writer` one page at
+    /// a time, so only the card images of a single page are held in memory at
+    /// once
+    pub fn print_to<W: Write>(&mut self, writer: W) -> Result<W, PrintError> {
+        self.skipped.clear();
+
+        // Distinct images in the document
         let mut images = Vec::new();
-        for png in pngs {
-            let reader = ImageReader::new(Cursor::new(&*png))
-                .with_guessed_format()
-                .context("could not guess image format")?;
-            let image = reader.decode().context("could not decode image")?;
-            let resized = image.resize_exact(WIDTH, HEIGHT, CatmullRom);
-            images.push(resized);
+        let mut add_image = |face: &CardFace, card: Option<usize>, back, adjustments, size| {
+            images.push(CardImage {
+                face: face.clone(),
+                card,
+                back,
+                adjustments,
+                size,
+            });
+            images.len() - 1
+        };
+        // The card back is sized to fit each size of card that uses it
+        let mut card_backs = Vec::new();
+        let mut slots = Vec::new();
+        for (idx, card) in self.cards.iter().enumerate() {
+            let adjustments = card.adjustments.unwrap_or(self.adjustments);
+            let front = add_image(&card.data, Some(idx), false, adjustments, card.size);
+            let back = match (&card.back, &self.card_back) {
+                (Some(face), _) => Some(add_image(face, Some(idx), true, adjustments, card.size)),
+                (None, Some(data)) => {
+                    let back = match card_backs.iter().find(|(size, _)| *size == card.size) {
+                        Some(&(_, back)) => back,
+                        None => {
+                            let face = CardFace::Image(data.clone());
+                            let back = add_image(&face, None, true, self.adjustments, card.size);
+                            card_backs.push((card.size, back));
+                            back
+                        }
+                    };
+                    Some(back)
+                }
+                (None, None) => None,
+            };
+            let slot = Slot {
+                card: idx,
+                front,
+                back,
+                size: card.size,
+            };
+            slots.extend((0..card.count).map(|_| slot));
+        }
+
+        // Sections of slots on each page
+        let pages = self
+            .layout(&slots)
+            .map_err(|err| PrintError::Layout(err.to_string()))?;
+
+        // Create final output
+        match self.output {
+            OutputFormat::Pdf => self.create_pdf(&images, &pages, writer),
+            _ => self.create_export(&images, &pages, writer),
+        }
+    }
+
+    /// Pack cards onto pages. Cards of each size are laid out in a grid, and
+    /// grids of smaller cards fill the space left below larger ones
+    fn layout(&self, slots: &[Slot]) -> Result<Vec<Vec<Section>>> {
+        let (_, page_height) = self.page.dimensions();
+        let (_, margin_y) = self.cut_guides.margin();
+        // Grids are spaced far enough apart to fit the cut guides of both
+        let gutter = self.gutter * POINTS_PER_MM * self.scale;
+        let spacing = gutter.max(2.0 * margin_y);
+
+        // Largest cards first, so smaller ones can fill the gaps
+        let mut sizes = Vec::<CardSize>::new();
+        for slot in slots {
+            if !sizes.contains(&slot.size) {
+                sizes.push(slot.size);
+            }
         }
+        sizes.sort_by(|a, b| {
+            let height = |size: &CardSize| size.dimensions().1;
+            height(b).total_cmp(&height(a))
+        });
 
-        // Create padding images
-        let mut padding = RgbaImage::new(WIDTH, HEIGHT);
-        for pixel in padding.pixels_mut() {
-            *pixel = Rgba([0xff, 0xff, 0xff, 0xff]);
+        let mut pages = Vec::<Vec<Section>>::new();
+        // Height taken up on the last page, including the margin at the top
+        let mut used = page_height;
+        for size in sizes {
+            let grid = self.grid(size)?;
+            let slots = slots
+                .iter()
+                .filter(|slot| slot.size == size)
+                .copied()
+                .collect::<Vec<_>>();
+            let mut remaining = &slots[..];
+            while !remaining.is_empty() {
+                // A new page fits the whole grid. Working it out again from
+                // the space left could round down to fewer rows
+                let rows = if pages.last().is_some_and(|page| page.is_empty()) {
+                    grid.rows
+                } else {
+                    let available = page_height - margin_y - used - spacing;
+                    let rows = ((available + gutter) / grid.pitch_y()).floor().max(0.0) as u32;
+                    rows.min(grid.rows)
+                };
+                if rows == 0 {
+                    pages.push(Vec::new());
+                    used = margin_y - spacing;
+                    continue;
+                }
+                let count = remaining.len().min((rows * grid.cols) as usize);
+                let rows = count.div_ceil(grid.cols as usize) as u32;
+                let section = Section {
+                    grid: grid.with_rows(rows, 0.0),
+                    slots: remaining[..count].to_vec(),
+                };
+                used += spacing + grid.height_of(rows);
+                pages.last_mut().unwrap().push(section);
+                remaining = &remaining[count..];
+            }
         }
-        while images.len() < CARD_COUNT as usize {
-            images.push(DynamicImage::from(padding.clone()));
+
+        // Grids on each page are stacked and centered. A page of one size uses
+        // the full grid, so its cut guides cover every position
+        for page in &mut pages {
+            if let [section] = &mut page[..] {
+                section.grid = self.grid(section.slots[0].size)?;
+                continue;
+            }
+            let height = page
+                .iter()
+                .map(|section| section.grid.height_of(section.grid.rows))
+                .sum::<f32>()
+                + spacing * (page.len() - 1) as f32;
+            let mut top = (page_height + height) / 2.0;
+            for section in page.iter_mut() {
+                section.grid = section.grid.with_rows(section.grid.rows, top);
+                top -= section.grid.height_of(section.grid.rows) + spacing;
+            }
         }
+        Ok(pages)
+    }
 
-        // Create page image
-        let mut page = RgbaImage::new(WIDTH * COLS + LINE_LEN * 2, HEIGHT * ROWS + LINE_LEN * 2);
-        for pixel in page.pixels_mut() {
-            *pixel = Rgba([0xff, 0xff, 0xff, 0xff]);
+    /// Shapes drawn on the front of a page of cards
+    fn page_shapes(&self, sections: &[Section]) -> Vec<Shape> {
+        let mut cards = Vec::new();
+        for Section { grid, slots } in sections {
+            let placements = slots
+                .iter()
+                .enumerate()
+                .map(|(idx, slot)| (grid.position(idx), slot.front))
+                .collect::<Vec<_>>();
+            cards.extend(card_shapes(*grid, &placements, false, self.black_bleed()));
         }
+        let mut guides = guide_shapes(sections, self.cut_guides);
 
-        // Overlay black background
-        let mut black = RgbaImage::new(
-            WIDTH * COLS + (2 * BLACK_BLEED),
-            HEIGHT * ROWS + (2 * BLACK_BLEED),
-        );
-        for pixel in black.pixels_mut() {
-            *pixel = Rgba([0x00, 0x00, 0x00, 0xff]);
-        }
-        overlay(
-            &mut page,
-            &black,
-            (LINE_LEN - BLACK_BLEED) as i64,
-            (LINE_LEN - BLACK_BLEED) as i64,
-        );
-        drop(black);
+        // Full length cut lines are drawn underneath the cards
+        if self.cut_guides == CutGuides::CutLines {
+            guides.extend(cards);
+            guides
+        } else {
+            cards.extend(guides);
+            cards
+        }
+    }
+
+    /// Shapes drawn on the back of a page of cards, mirrored so that each back
+    /// lines up with its front
+    fn back_page_shapes(&self, sections: &[Section]) -> Vec<Shape> {
+        let mirror = MirrorAxis::new(&self.page, self.duplex);
+        // Flipping the paper top to bottom turns the backs upside down
+        let rotated = mirror == MirrorAxis::Vertical;
+        let mut shapes = Vec::new();
+        for Section { grid, slots } in sections {
+            let cards = slots
+                .iter()
+                .enumerate()
+                .filter_map(|(idx, slot)| {
+                    let position = grid.back_position(idx, mirror, self.back_offset);
+                    slot.back.map(|back| (position, back))
+                })
+                .collect::<Vec<_>>();
+            shapes.extend(card_shapes(*grid, &cards, rotated, self.black_bleed()));
+        }
+        shapes
+    }
 
-        // Overlay images
-        for j in 0..ROWS {
-            for i in 0..COLS {
-                let x = (i * WIDTH + LINE_LEN) as i64;
-                let y = (j * HEIGHT + LINE_LEN) as i64;
-                let idx = (j * COLS + i) as usize;
-                overlay(&mut page, &images[idx], x, y);
+    /// Names and counts of the cards on a page, in the order they appear.
+    /// Cards without a name are numbered from 1
+    fn page_cards(&self, sections: &[Section]) -> Vec<(String, u32)> {
+        let mut cards = Vec::<(String, u32)>::new();
+        for slot in sections.iter().flat_map(|section| section.slots.iter()) {
+            let name = (self.cards[slot.card].name.clone())
+                .unwrap_or_else(|| format!("Card {}", slot.card + 1));
+            match cards.iter_mut().find(|(other, _)| *other == name) {
+                Some((_, count)) => *count += 1,
+                None => cards.push((name, 1)),
             }
         }
+        cards
+    }
+
+    /// Baseline of the footer, centered below the cards and cut guides of a
+    /// page, or `None` if there is no room for it above the page margin
+    fn footer_baseline(&self, sections: &[Section]) -> Option<f32> {
+        let (_, margin_y) = self.cut_guides.margin();
+        let bottom = (sections.iter())
+            .map(|section| section.grid.bottom())
+            .fold(f32::MAX, f32::min)
+            - margin_y;
+        // The font size is an upper bound of the height of the text
+        let y = ((bottom - FOOTER_SIZE) / 2.0).max(FOOTER_MARGIN);
+        (y + FOOTER_SIZE <= bottom).then_some(y)
+    }
 
-        // Draw cut lines
-        for i in 0..=COLS {
-            for j in 0..=ROWS {
-                let x = LINE_LEN + i * WIDTH;
-                let y = LINE_LEN + j * HEIGHT;
-                let rect = Rect::at((x - LINE_LEN) as i32, (y - LINE_WIDTH) as i32)
-                    .of_size(LINE_LEN * 2, LINE_WIDTH * 2);
-                draw_filled_rect_mut(&mut page, rect, LINE_COLOR);
-                let rect = Rect::at((x - LINE_WIDTH) as i32, (y - LINE_LEN) as i32)
-                    .of_size(LINE_WIDTH * 2, LINE_LEN * 2);
-                draw_filled_rect_mut(&mut page, rect, LINE_COLOR);
+    /// Operations drawing `text` centered on the page at baseline `y`,
+    /// shortened to fit the width of the page
+    fn draw_footer(&self, font: &mut Font, y: f32, text: &str) -> Vec<Operation> {
+        let (page_width, _) = self.page.dimensions();
+        let (margin_x, _) = self.cut_guides.margin();
+
+        // Names that don't fit are cut off with an ellipsis
+        let mut text = text.to_string();
+        let available = page_width - 2.0 * margin_x.max(FOOTER_MARGIN);
+        if font.width(&text, FOOTER_SIZE) > available {
+            let ellipsis = font.width("…", FOOTER_SIZE);
+            while !text.is_empty() && font.width(&text, FOOTER_SIZE) + ellipsis > available {
+                text.pop();
             }
+            text.push('…');
         }
-        let image = DynamicImage::ImageRgba8(page);
+        let position = ((page_width - font.width(&text, FOOTER_SIZE)) / 2.0, y);
+
+        let mut operations = vec![
+            Operation::new("q", vec![]),
+            Operation::new("g", vec![FOOTER_GRAY.into()]),
+        ];
+        operations.extend(font.draw(&text, FOOTER_SIZE, position));
+        operations.push(Operation::new("Q", vec![]));
+        operations
+    }
 
-        Ok(DynamicImage::from(image.into_rgb8()))
+    /// Operations drawing the stamp on the front of every card of a page.
+    /// `stamp` draws it centered on the origin at its natural `size`
+    fn draw_stamps(
+        &self,
+        sections: &[Section],
+        stamp: &[Operation],
+        (width, height): (f32, f32),
+    ) -> Vec<Operation> {
+        let ((x, y), (box_width, box_height)) = self.stamp_position.frame();
+        let (sin, cos) = self.stamp_rotation.to_radians().sin_cos();
+        let mut operations = Vec::new();
+        for Section { grid, slots } in sections {
+            let scale =
+                (box_width * grid.card_width / width).min(box_height * grid.card_height / height);
+            for idx in 0..slots.len() {
+                let (left, bottom) = grid.position(idx);
+                let center = (left + x * grid.card_width, bottom + y * grid.card_height);
+                operations.push(Operation::new("q", vec![]));
+                operations.push(Operation::new(
+                    "cm",
+                    vec![
+                        (scale * cos).into(),
+                        (scale * sin).into(),
+                        (-scale * sin).into(),
+                        (scale * cos).into(),
+                        center.0.into(),
+                        center.1.into(),
+                    ],
+                ));
+                operations.push(Operation::new("gs", vec![STAMP_NAME.into()]));
+                operations.extend_from_slice(stamp);
+                operations.push(Operation::new("Q", vec![]));
+            }
+        }
+        operations
     }
 
-    fn create_pdf(&mut self, pages: Vec<DynamicImage>) -> Result<Vec<u8>> {
+    fn create_pdf<W: Write>(
+        &mut self,
+        images: &[CardImage],
+        pages: &[Vec<Section>],
+        writer: W,
+    ) -> Result<W, PrintError> {
         // Set up document
-        let mut doc = Document::new();
+        let mut pdf = PdfWriter::new(writer)?;
+        let (page_width, page_height) = self.page.dimensions();
 
         // Pages object id
-        let pages_id = doc.new_object_id();
+        let pages_id = pdf.reserve();
+
+        // Color profile of the output, used by images and the output intent
+        let icc_id = match self.color.icc_profile()? {
+            Some((profile, dict)) => {
+                let mut stream = Stream::new(dict, profile);
+                stream.compress()?;
+                Some(pdf.add(stream)?)
+            }
+            None => None,
+        };
+        let rgb_color_space = match (&self.color, icc_id) {
+            (ColorOutput::Srgb, Some(icc_id)) => vec!["ICCBased".into(), icc_id.into()].into(),
+            _ => Object::from("DeviceRGB"),
+        };
+
+        // Ids of images that have already been written
+        let mut image_ids = vec![None; images.len()];
+
+        // Font of text proxies, written at the end once every glyph it is
+        // used with is known
+        let mut font: Option<(Font, ObjectId)> = None;
+
+        // Slots on every page
+        let slots = || (pages.iter().flatten()).flat_map(|section| section.slots.iter());
+
+        // Back pages are only needed if any card has a back
+        let double_sided = slots().any(|slot| slot.back.is_some());
 
         // List of page ids
         let mut page_ids = Vec::<Object>::new();
+        // Front page ids and the cards on them, for the outline
+        let mut outline = Vec::new();
 
-        let len = pages.len();
-        for (i, img) in pages.into_iter().enumerate() {
-            // Image stream object
-            let (width, height) = img.dimensions();
-            let img_dict = dictionary! {
-                "Type" => "XObject",
-                "Subtype" => "Image",
-                "Width" => width,
-                "Height" => height,
-                "ColorSpace" => "DeviceRGB",
-                "BitsPerComponent" => 8,
-            };
-            let mut img_stream = Stream::new(img_dict, img.as_bytes().to_vec());
-            drop(img);
-            self.report_progress(&format!("Compressing pages ({} / {})", i + 1, len))?;
-            img_stream.compress()?;
-            let img_id = doc.add_object(img_stream);
-
-            // Resources object
-            let resources_dict = dictionary! {
-                "ProcSet" => vec!["PDF".into(), "ImageB".into()],
-                "XObject" => dictionary! {
-                    "I1" => img_id,
-                },
-            };
-            let resources_id = doc.add_object(resources_dict);
-
-            let card_width = width * 72 / 298;
-            let card_height = height * 72 / 297;
-            let card_tx = (PAGE_WIDTH - card_width) / 2;
-            let card_ty = (PAGE_HEIGHT - card_height) / 2;
+        // Number of images that will be written
+        let mut used = vec![false; images.len()];
+        for slot in slots() {
+            used[slot.front] = true;
+            if let Some(back) = slot.back {
+                used[back] = true;
+            }
+        }
+        let image_count = used.iter().filter(|&&used| used).count() as u32;
+        let mut images_written = 0;
+        let page_count = pages.len() as u32;
+        let options = self.image_options();
+        // Bleed of text proxies, which are drawn at full scale
+        let proxy_bleed = self.bleed_pixels() as f32 * CARD_WIDTH_MM * POINTS_PER_MM / WIDTH as f32;
 
-            // Page content instructions
-            let content = Content {
-                operations: vec![
+        // Operations drawing the stamp centered on the origin, and its
+        // natural size. Logos are written once and shared by every page
+        let mut stamp_logo_id = None;
+        let stamp = match &self.stamp {
+            Some(Stamp::Text(text)) => {
+                let (font, _) = match &mut font {
+                    Some(font) => font,
+                    None => font.insert((Font::new()?, pdf.reserve())),
+                };
+                let (width, height) = (font.width(text, 1.0), font.cap_height());
+                let mut operations = vec![Operation::new("g", vec![STAMP_GRAY.into()])];
+                operations.extend(font.draw(text, 1.0, (-width / 2.0, -height / 2.0)));
+                Some((operations, (width, height)))
+            }
+            Some(Stamp::Logo(logo)) => {
+                let encoded = options.encode_pixels(logo.clone())?;
+                stamp_logo_id = Some(write_image(&mut pdf, encoded, &rgb_color_space)?);
+                let width = logo.width() as f32 / logo.height() as f32;
+                let operations = vec![
                     Operation::new(
                         "cm",
                         vec![
-                            card_width.into(),
+                            width.into(),
                             0.into(),
                             0.into(),
-                            card_height.into(),
-                            card_tx.into(),
-                            card_ty.into(),
+                            1.into(),
+                            (-width / 2.0).into(),
+                            (-0.5).into(),
                         ],
                     ),
-                    Operation::new("Do", vec!["I1".into()]),
-                ],
-            };
-            let content_id = doc.add_object(Stream::new(dictionary! {}, content.encode().unwrap()));
-
-            // Page object
-            let page_dict = dictionary! {
-                "Type" => "Page",
-                "Parent" => pages_id,
-                "Contents" => content_id,
-                "Resources" => resources_id,
-            };
-            let page_id = doc.add_object(page_dict);
-            page_ids.push(page_id.into());
+                    Operation::new("Do", vec![STAMP_NAME.into()]),
+                ];
+                Some((operations, (width, 1.0)))
+            }
+            None => None,
+        };
+        // Whether front pages draw text with the font
+        let page_font = self.footer || matches!(self.stamp, Some(Stamp::Text(_)));
+
+        // Images used by the front and back of a page
+        let page_images = |sections: &[Section]| {
+            let slots = || sections.iter().flat_map(|section| section.slots.iter());
+            let fronts = slots().map(|slot| slot.front).collect::<Vec<_>>();
+            let backs = slots().filter_map(|slot| slot.back).collect::<Vec<_>>();
+            if double_sided {
+                vec![fronts, backs]
+            } else {
+                vec![fronts]
+            }
+        };
+
+        let mut next = 0;
+        while next < pages.len() {
+            // Each image is written before the first page that uses it, and
+            // shared by every later page. Pages are taken until there are
+            // enough new images to keep every thread busy
+            let mut end = next;
+            let mut pending = Vec::new();
+            while end < pages.len() && (end == next || pending.len() < encode_threads()) {
+                for idx in page_images(&pages[end]).concat() {
+                    if image_ids[idx].is_none() && !pending.contains(&idx) {
+                        pending.push(idx);
+                    }
+                }
+                end += 1;
+            }
+            let encoded = encode_images(&options, images, &pending);
+            for (idx, result) in pending.into_iter().zip(encoded) {
+                let (face, skipped) = result?;
+                self.skipped.extend(skipped);
+                let id = match face {
+                    EncodedFace::Image(image) => write_image(&mut pdf, *image, &rgb_color_space)?,
+                    EncodedFace::Text(text) => {
+                        let (font, font_id) = match &mut font {
+                            Some(font) => font,
+                            None => font.insert((Font::new()?, pdf.reserve())),
+                        };
+                        let size = images[idx].size;
+                        let form = text_proxy(&text, font, *font_id, size, proxy_bleed)?;
+                        pdf.add(form)?
+                    }
+                };
+                image_ids[idx] = Some(id);
+                images_written += 1;
+                self.report_progress(Progress {
+                    phase: Phase::Images,
+                    current: images_written,
+                    total: image_count,
+                    bytes: pdf.position(),
+                })?;
+            }
+
+            for (i, sections) in pages.iter().enumerate().take(end).skip(next) {
+                let cards = self.page_cards(sections);
+                let mut front = Content {
+                    operations: draw_shapes(&self.page_shapes(sections)),
+                };
+                if let Some((stamp, size)) = &stamp {
+                    front
+                        .operations
+                        .extend(self.draw_stamps(sections, stamp, *size));
+                }
+                if let Some(y) = self.footer_baseline(sections).filter(|_| self.footer) {
+                    let (font, _) = match &mut font {
+                        Some(font) => font,
+                        None => font.insert((Font::new()?, pdf.reserve())),
+                    };
+                    let cards = (cards.iter())
+                        .map(|(name, count)| format!("{count} {name}"))
+                        .collect::<Vec<_>>();
+                    let text = format!("Page {} of {page_count}: {}", i + 1, cards.join(", "));
+                    front.operations.extend(self.draw_footer(font, y, &text));
+                }
+                let mut contents = vec![front];
+                if double_sided {
+                    contents.push(Content {
+                        operations: draw_shapes(&self.back_page_shapes(sections)),
+                    });
+                }
+                let sides = contents.into_iter().zip(page_images(sections));
+                for (side, (content, used)) in sides.enumerate() {
+                    let mut xobjects = Dictionary::new();
+                    for idx in used {
+                        if let Some(image_id) = image_ids[idx] {
+                            xobjects.set(image_name(idx), image_id);
+                        }
+                    }
+                    if let (0, Some(logo_id)) = (side, stamp_logo_id) {
+                        xobjects.set(STAMP_NAME, logo_id);
+                    }
+
+                    // Page content instructions
+                    let mut content_stream = Stream::new(dictionary! {}, content.encode()?);
+                    content_stream.compress()?;
+                    let content_id = pdf.add(content_stream)?;
+
+                    // Page object
+                    let mut resources = dictionary! {
+                        "ProcSet" => vec!["PDF".into(), "ImageB".into(), "ImageC".into()],
+                        "XObject" => xobjects,
+                    };
+                    if let (0, true, Some((_, font_id))) = (side, page_font, &font) {
+                        resources.set("Font", dictionary! { font::RESOURCE_NAME => *font_id });
+                    }
+                    if side == 0 && stamp.is_some() {
+                        let state = dictionary! {
+                            "Type" => "ExtGState",
+                            "ca" => self.stamp_opacity,
+                            "CA" => self.stamp_opacity,
+                        };
+                        resources.set("ExtGState", dictionary! { STAMP_NAME => state });
+                    }
+                    let page_dict = dictionary! {
+                        "Type" => "Page",
+                        "Parent" => pages_id,
+                        "Contents" => content_id,
+                        "Resources" => resources,
+                    };
+                    let page_id = pdf.add(page_dict)?;
+                    page_ids.push(page_id.into());
+                    if side == 0 {
+                        outline.push((page_id, cards.clone()));
+                    }
+                }
+
+                self.report_progress(Progress {
+                    phase: Phase::Pages,
+                    current: i as u32 + 1,
+                    total: page_count,
+                    bytes: pdf.position(),
+                })?;
+            }
+            next = end;
+        }
+        self.report_progress(Progress {
+            phase: Phase::Finishing,
+            current: 0,
+            total: 1,
+            bytes: pdf.position(),
+        })?;
+
+        if let Some((font, font_id)) = font {
+            font.write(&mut pdf, font_id)?;
         }
 
         // Pages object
-        let page_count = page_ids.len() as i64;
+        let kids_count = page_ids.len() as i64;
         let pages = dictionary! {
             "Type" => "Pages",
             "Kids" => page_ids,
-            "Count" => page_count,
+            "Count" => kids_count,
             "MediaBox" =>
                 vec![
                     0.into(),
                     0.into(),
-                    PAGE_WIDTH.into(),
-                    PAGE_HEIGHT.into(),
+                    page_width.into(),
+                    page_height.into(),
                 ],
         };
-        doc.set_object(pages_id, pages);
+        pdf.set(pages_id, pages)?;
 
         // Catalog object
-        let catalog_id = doc.add_object(dictionary! {
+        let mut catalog = dictionary! {
             "Type" => "Catalog",
             "Pages" => pages_id,
+        };
+        if !outline.is_empty() {
+            let outline_id = write_outline(&mut pdf, &outline)?;
+            catalog.set("Outlines", outline_id);
+            catalog.set("PageMode", "UseOutlines");
+        }
+        // Back pages are labelled with the number of their front page
+        if double_sided {
+            let mut labels = Vec::<Object>::new();
+            for i in 0..page_count as i64 {
+                labels.push((2 * i).into());
+                labels.push(dictionary! { "S" => "D", "St" => i + 1 }.into());
+                labels.push((2 * i + 1).into());
+                labels.push(
+                    dictionary! {
+                        "S" => "D",
+                        "St" => i + 1,
+                        "P" => Object::string_literal("Back "),
+                    }
+                    .into(),
+                );
+            }
+            catalog.set("PageLabels", dictionary! { "Nums" => labels });
+        }
+
+        // Document information
+        let date = pdf::date_string(self.creation_date.unwrap_or_else(now));
+        let info = dictionary! {
+            "Title" => pdf::text_string(&self.title),
+            "Creator" => Object::string_literal(CREATOR),
+            "Producer" => Object::string_literal(CREATOR),
+            "CreationDate" => date.clone(),
+            "ModDate" => date,
+        };
+        // The output intent describes how colors were prepared, without
+        // claiming pdf/x conformance, which forbids the soft masks and
+        // transparency used for card corners and stamps
+        if let Some(icc_id) = icc_id {
+            let output_intent = self.color.output_intent(icc_id.into());
+            catalog.set("OutputIntents", vec![output_intent.into()]);
+        }
+        let info_id = pdf.add(info)?;
+        let catalog_id = pdf.add(catalog)?;
+        let trailer = dictionary! {
+            "Root" => catalog_id,
+            "Info" => info_id,
+        };
+
+        // Write cross reference table
+        Ok(pdf.finish(trailer)?)
+    }
+}
+
+/// Write an outline with an entry for each page, and the cards on it below
+/// each entry
+fn write_outline<W: Write>(
+    pdf: &mut PdfWriter<W>,
+    pages: &[(ObjectId, Vec<(String, u32)>)],
+) -> Result<ObjectId> {
+    let root_id = pdf.reserve();
+    let item_ids = pages.iter().map(|_| pdf.reserve()).collect::<Vec<_>>();
+    for (i, ((page_id, cards), &item_id)) in pages.iter().zip(&item_ids).enumerate() {
+        let dest = || vec![(*page_id).into(), "Fit".into()];
+        let card_ids = cards.iter().map(|_| pdf.reserve()).collect::<Vec<_>>();
+        for (j, ((name, count), &card_id)) in cards.iter().zip(&card_ids).enumerate() {
+            let mut card = dictionary! {
+                "Title" => pdf::text_string(&format!("{count} {name}")),
+                "Parent" => item_id,
+                "Dest" => dest(),
+            };
+            link_siblings(&mut card, &card_ids, j);
+            pdf.set(card_id, card)?;
+        }
+
+        let mut item = dictionary! {
+            "Title" => pdf::text_string(&format!("Page {}", i + 1)),
+            "Parent" => root_id,
+            "Dest" => dest(),
+        };
+        if let (Some(&first), Some(&last)) = (card_ids.first(), card_ids.last()) {
+            item.set("First", first);
+            item.set("Last", last);
+            // Negative counts start closed
+            item.set("Count", -(card_ids.len() as i64));
+        }
+        link_siblings(&mut item, &item_ids, i);
+        pdf.set(item_id, item)?;
+    }
+
+    let mut root = dictionary! {
+        "Type" => "Outlines",
+        "Count" => item_ids.len() as i64,
+    };
+    if let (Some(&first), Some(&last)) = (item_ids.first(), item_ids.last()) {
+        root.set("First", first);
+        root.set("Last", last);
+    }
+    pdf.set(root_id, root)?;
+    Ok(root_id)
+}
+
+/// Link the outline item at `idx` to the items before and after it
+fn link_siblings(item: &mut Dictionary, ids: &[ObjectId], idx: usize) {
+    if idx > 0 {
+        item.set("Prev", ids[idx - 1]);
+    }
+    if let Some(&next) = ids.get(idx + 1) {
+        item.set("Next", next);
+    }
+}
+
+/// Current time in seconds since the Unix epoch
+fn now() -> i64 {
+    #[cfg(target_arch = "wasm32")]
+    return (js_sys::Date::now() / 1000.0) as i64;
+    #[cfg(not(target_arch = "wasm32"))]
+    return std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs() as i64);
+}
+
+/// Cut guides for a page of sections of cards
+fn guide_shapes(sections: &[Section], style: CutGuides) -> Vec<Shape> {
+    if style == CutGuides::None {
+        return Vec::new();
+    }
+    let mut shapes = Vec::new();
+    let mut paths = Vec::new();
+    let mut rects = Vec::new();
+    let mut width = LINE_WIDTH;
+    let mut dash = None;
+
+    if style == CutGuides::RegistrationMarks {
+        // One set of marks around every section, since the cutter scans the
+        // page for a single set
+        if let (Some(first), Some(last)) = (sections.first(), sections.last()) {
+            let grids = || sections.iter().map(|section| section.grid);
+            let left = grids().map(|grid| grid.left).fold(f32::MAX, f32::min);
+            let right = grids().map(|grid| grid.right()).fold(f32::MIN, f32::max);
+            let (top, bottom) = (first.grid.top, last.grid.bottom());
+            shapes.extend(registration_marks((left, top), (right, bottom)));
+        }
+    }
+
+    for (k, Section { grid, slots }) in sections.iter().enumerate() {
+        let (grid, count) = (*grid, slots.len());
+        let (right, bottom) = (grid.right(), grid.bottom());
+        let xs = grid.trim_xs();
+        let ys = grid.trim_ys();
+
+        match style {
+            CutGuides::None => unreachable!(),
+            // Drawn once for the whole page above
+            CutGuides::RegistrationMarks => {}
+            CutGuides::CropMarks if grid.bleed == 0.0 && grid.gutter > 0.0 => {
+                // Marks pointing away from each corner, stopping at the
+                // neighbouring card so each card can be trimmed on its own
+                let len = |neighbour: bool| {
+                    if neighbour {
+                        LINE_LEN.min(grid.gutter)
+                    } else {
+                        LINE_LEN
+                    }
+                };
+                for idx in 0..count {
+                    let (x, y) = grid.position(idx);
+                    let (i, j) = (idx as u32 % grid.cols, idx as u32 / grid.cols);
+                    let horizontal = [
+                        (x, -len(i > 0)),
+                        (x + grid.card_width, len(i + 1 < grid.cols)),
+                    ];
+                    let vertical = [
+                        (y, -len(j + 1 < grid.rows)),
+                        (y + grid.card_height, len(j > 0)),
+                    ];
+                    for (x, dx) in horizontal {
+                        for (y, dy) in vertical {
+                            paths.push(vec![(x, y), (x + dx, y)]);
+                            paths.push(vec![(x, y), (x, y + dy)]);
+                        }
+                    }
+                }
+            }
+            CutGuides::CropMarks if grid.bleed == 0.0 => {
+                // Crosses at each grid corner
+                for &x in &xs {
+                    for &y in &ys {
+                        paths.push(vec![(x - LINE_LEN, y), (x + LINE_LEN, y)]);
+                        paths.push(vec![(x, y - LINE_LEN), (x, y + LINE_LEN)]);
+                    }
+                }
+            }
+            CutGuides::CropMarks => {
+                // Marks in the margin around the grid, in line with each card edge
+                for &x in &xs {
+                    paths.push(vec![(x, grid.top), (x, grid.top + LINE_LEN)]);
+                    paths.push(vec![(x, bottom), (x, bottom - LINE_LEN)]);
+                }
+                for &y in &ys {
+                    paths.push(vec![(grid.left, y), (grid.left - LINE_LEN, y)]);
+                    paths.push(vec![(right, y), (right + LINE_LEN, y)]);
+                }
+            }
+            CutGuides::CutLines => {
+                // Dashed lines across the whole page. Vertical lines stop at
+                // the edge of their section, so they don't cross other cards
+                dash = Some(DASH_LEN);
+                let top = if k == 0 { grid.page_height } else { ys[0] };
+                let bottom = if k + 1 == sections.len() {
+                    0.0
+                } else {
+                    ys[ys.len() - 1]
+                };
+                for &x in &xs {
+                    paths.push(vec![(x, bottom), (x, top)]);
+                }
+                for &y in &ys {
+                    paths.push(vec![(0.0, y), (grid.page_width, y)]);
+                }
+            }
+            CutGuides::Outlines => {
+                // Thinnest line the output device can draw around each card
+                width = 0.0;
+                for idx in 0..count {
+                    let (x, y) = grid.position(idx);
+                    rects.push([x, y, grid.card_width, grid.card_height]);
+                }
+            }
+        }
+    }
+
+    if !paths.is_empty() || !rects.is_empty() {
+        shapes.push(Shape::Stroke {
+            gray: LINE_GRAY,
+            width,
+            dash,
+            paths,
+            rects,
+        });
+    }
+    shapes
+}
+
+/// Registration marks around the area from `(left, top)` to `(right, bottom)`
+fn registration_marks((left, top): (f32, f32), (right, bottom): (f32, f32)) -> [Shape; 2] {
+    // Solid black marks in the margin for cutting machine sensors: a
+    // square at the top left, and corners at the top right and bottom left
+    let size = REGISTRATION_SIZE_MM * POINTS_PER_MM;
+    let len = REGISTRATION_LEN_MM * POINTS_PER_MM;
+    let gap = REGISTRATION_GAP_MM * POINTS_PER_MM;
+    let (left, top) = (left - gap, top + gap);
+    let (right, bottom) = (right + gap, bottom - gap);
+    [
+        Shape::Fill {
+            gray: 0.0,
+            rects: vec![[left - size, top - size, size, size]],
+        },
+        Shape::Stroke {
+            gray: 0.0,
+            width: REGISTRATION_WIDTH_MM * POINTS_PER_MM,
+            dash: None,
+            paths: vec![
+                vec![(right - len, top), (right, top), (right, top - len)],
+                vec![(left, bottom + len), (left, bottom), (left + len, bottom)],
+            ],
+            rects: Vec::new(),
+        },
+    ]
+}
+
+/// Add a straight line to the current path
+fn line(operations: &mut Vec<Operation>, from: (f32, f32), to: (f32, f32)) {
+    operations.push(Operation::new("m", vec![from.0.into(), from.1.into()]));
+    operations.push(Operation::new("l", vec![to.0.into(), to.1.into()]));
+}
+
+/// Card images with black bleed behind them, given the bottom left corner of
+/// each card and the index of its image
+fn card_shapes(
+    grid: Grid,
+    cards: &[((f32, f32), usize)],
+    rotated: bool,
+    black_bleed: f32,
+) -> Vec<Shape> {
+    let mut shapes = Vec::new();
+
+    // Black bleed behind each card
+    if black_bleed > 0.0 && !cards.is_empty() {
+        let rects = cards
+            .iter()
+            .map(|&((x, y), _)| {
+                [
+                    x - black_bleed,
+                    y - black_bleed,
+                    grid.card_width + 2.0 * black_bleed,
+                    grid.card_height + 2.0 * black_bleed,
+                ]
+            })
+            .collect();
+        shapes.push(Shape::Fill { gray: 0.0, rects });
+    }
+
+    // Card images including their generated bleed, optionally rotated 180
+    // degrees around their center
+    for &((x, y), idx) in cards {
+        let (x, y) = (x - grid.bleed, y - grid.bleed);
+        let (width, height) = (grid.cell_width(), grid.cell_height());
+        let matrix = if rotated {
+            [-width, -height, x + width, y + height]
+        } else {
+            [width, height, x, y]
+        };
+        shapes.push(Shape::Image { idx, matrix });
+    }
+
+    shapes
+}
+
+/// Content stream operations drawing shapes
+fn draw_shapes(shapes: &[Shape]) -> Vec<Operation> {
+    let mut operations = Vec::new();
+    for shape in shapes {
+        operations.push(Operation::new("q", vec![]));
+        match shape {
+            Shape::Fill { gray, rects } => {
+                operations.push(Operation::new("g", vec![(*gray).into()]));
+                for &[x, y, width, height] in rects {
+                    let operands = vec![x.into(), y.into(), width.into(), height.into()];
+                    operations.push(Operation::new("re", operands));
+                }
+                operations.push(Operation::new("f", vec![]));
+            }
+            Shape::Stroke {
+                gray,
+                width,
+                dash,
+                paths,
+                rects,
+            } => {
+                operations.push(Operation::new("G", vec![(*gray).into()]));
+                operations.push(Operation::new("w", vec![(*width).into()]));
+                if let Some(dash) = *dash {
+                    operations.push(Operation::new(
+                        "d",
+                        vec![vec![dash.into(), dash.into()].into(), 0.into()],
+                    ));
+                }
+                for path in paths {
+                    for (i, &(x, y)) in path.iter().enumerate() {
+                        let operator = if i == 0 { "m" } else { "l" };
+                        operations.push(Operation::new(operator, vec![x.into(), y.into()]));
+                    }
+                }
+                for &[x, y, width, height] in rects {
+                    let operands = vec![x.into(), y.into(), width.into(), height.into()];
+                    operations.push(Operation::new("re", operands));
+                }
+                operations.push(Operation::new("S", vec![]));
+            }
+            &Shape::Image {
+                idx,
+                matrix: [a, d, e, f],
+            } => {
+                operations.push(Operation::new(
+                    "cm",
+                    vec![a.into(), 0.into(), 0.into(), d.into(), e.into(), f.into()],
+                ));
+                operations.push(Operation::new(
+                    "Do",
+                    vec![Object::Name(image_name(idx).into_bytes())],
+                ));
+            }
+        }
+        operations.push(Operation::new("Q", vec![]));
+    }
+    operations
+}
+
+/// Form XObject drawing a text proxy of a card, scaled to the unit square
+/// like card images
+fn text_proxy(
+    card: &TextCard,
+    font: &mut Font,
+    font_id: ObjectId,
+    size: CardSize,
+    bleed: f32,
+) -> Result<Stream> {
+    // Laid out at full scale, and scaled with the rest of the card
+    let (width, height) = card_size(size, 1.0);
+    let (cell_width, cell_height) = (width + 2.0 * bleed, height + 2.0 * bleed);
+
+    // White card, including its bleed
+    let mut operations = vec![
+        Operation::new("g", vec![1.into()]),
+        Operation::new(
+            "re",
+            vec![0.into(), 0.into(), cell_width.into(), cell_height.into()],
+        ),
+        Operation::new("f", vec![]),
+        Operation::new(
+            "cm",
+            vec![
+                1.into(),
+                0.into(),
+                0.into(),
+                1.into(),
+                bleed.into(),
+                bleed.into(),
+            ],
+        ),
+    ];
+
+    // Frame, divided into the title bar, type line and text box
+    let (left, right) = (PROXY_MARGIN, width - PROXY_MARGIN);
+    let (bottom, top) = (PROXY_MARGIN, height - PROXY_MARGIN);
+    let title_bottom = top - PROXY_BAR_HEIGHT;
+    let type_bottom = title_bottom - PROXY_BAR_HEIGHT;
+    operations.push(Operation::new("g", vec![0.into()]));
+    operations.push(Operation::new("G", vec![0.into()]));
+    operations.push(Operation::new("w", vec![PROXY_FRAME_WIDTH.into()]));
+    operations.push(Operation::new(
+        "re",
+        vec![
+            left.into(),
+            bottom.into(),
+            (right - left).into(),
+            (top - bottom).into(),
+        ],
+    ));
+    operations.push(Operation::new("S", vec![]));
+    let mut rules = Vec::new();
+    line(&mut rules, (left, title_bottom), (right, title_bottom));
+    line(&mut rules, (left, type_bottom), (right, type_bottom));
+    operations.push(Operation::new("w", vec![PROXY_RULE_WIDTH.into()]));
+    operations.extend(rules);
+    operations.push(Operation::new("S", vec![]));
+
+    // Text is vertically centered in its bar
+    let (text_left, text_right) = (left + PROXY_PADDING, right - PROXY_PADDING);
+    let text_width = text_right - text_left;
+    let cap_height = font.cap_height();
+    let baseline =
+        |bar_bottom: f32, size: f32| bar_bottom + (PROXY_BAR_HEIGHT - cap_height * size) / 2.0;
+
+    // Name in bold (stroked outlines), with the mana cost right aligned
+    let cost = card
+        .mana_cost
+        .as_deref()
+        .map(mana_symbols)
+        .unwrap_or_default();
+    let cost_width = font.width(&cost, PROXY_TITLE_SIZE);
+    let name_width = text_width - cost_width - PROXY_PADDING;
+    let name_size = fit_size(font, &card.name, PROXY_TITLE_SIZE, name_width);
+    let name_baseline = baseline(title_bottom, name_size);
+    let cost_baseline = baseline(title_bottom, PROXY_TITLE_SIZE);
+    operations.push(Operation::new("Tr", vec![2.into()]));
+    operations.push(Operation::new("w", vec![PROXY_BOLD_WIDTH.into()]));
+    operations.extend(font.draw(&card.name, name_size, (text_left, name_baseline)));
+    operations.push(Operation::new("Tr", vec![0.into()]));
+    if !cost.is_empty() {
+        let x = text_right - cost_width;
+        operations.extend(font.draw(&cost, PROXY_TITLE_SIZE, (x, cost_baseline)));
+    }
+
+    if let Some(type_line) = &card.type_line {
+        let size = fit_size(font, type_line, PROXY_TYPE_SIZE, text_width);
+        let y = baseline(type_bottom, size);
+        operations.extend(font.draw(type_line, size, (text_left, y)));
+    }
+
+    // Power and toughness in a box in the bottom right corner
+    let mut text_bottom = bottom + PROXY_PADDING;
+    if card.power.is_some() || card.toughness.is_some() {
+        let power = card.power.as_deref().unwrap_or_default();
+        let toughness = card.toughness.as_deref().unwrap_or_default();
+        let stats = format!("{power}/{toughness}");
+        let box_left = right - PROXY_STATS_WIDTH;
+        let box_bottom = bottom + PROXY_PADDING;
+        operations.push(Operation::new(
+            "re",
+            vec![
+                box_left.into(),
+                box_bottom.into(),
+                (PROXY_STATS_WIDTH - PROXY_PADDING).into(),
+                PROXY_BAR_HEIGHT.into(),
+            ],
+        ));
+        operations.push(Operation::new("S", vec![]));
+        let box_width = PROXY_STATS_WIDTH - PROXY_PADDING;
+        let size = fit_size(font, &stats, PROXY_TITLE_SIZE, box_width - PROXY_PADDING);
+        let x = box_left + (box_width - font.width(&stats, size)) / 2.0;
+        let y = baseline(box_bottom, size);
+        operations.extend(font.draw(&stats, size, (x, y)));
+        text_bottom = box_bottom + PROXY_BAR_HEIGHT + PROXY_PADDING;
+    }
+
+    // Rules text, shrunk until it fits the text box and clipped to it if it
+    // still doesn't
+    if let Some(oracle_text) = &card.oracle_text {
+        let text_top = type_bottom - PROXY_PADDING;
+        let available = text_top - text_bottom;
+        let mut size = PROXY_TEXT_SIZE;
+        let mut paragraphs = wrap_text(font, oracle_text, size, text_width);
+        while size > PROXY_MIN_TEXT_SIZE && text_height(&paragraphs, size) > available {
+            size -= PROXY_TEXT_STEP;
+            paragraphs = wrap_text(font, oracle_text, size, text_width);
+        }
+        operations.push(Operation::new("q", vec![]));
+        operations.push(Operation::new(
+            "re",
+            vec![
+                text_left.into(),
+                text_bottom.into(),
+                text_width.into(),
+                available.into(),
+            ],
+        ));
+        operations.push(Operation::new("W", vec![]));
+        operations.push(Operation::new("n", vec![]));
+        let mut y = text_top - size;
+        for paragraph in paragraphs {
+            for line in paragraph {
+                operations.extend(font.draw(&line, size, (text_left, y)));
+                y -= size * PROXY_LEADING;
+            }
+            y -= size * PROXY_PARAGRAPH_GAP;
+        }
+        operations.push(Operation::new("Q", vec![]));
+    }
+
+    let dict = dictionary! {
+        "Type" => "XObject",
+        "Subtype" => "Form",
+        "BBox" => vec![0.into(), 0.into(), cell_width.into(), cell_height.into()],
+        "Matrix" => vec![
+            (1.0 / cell_width).into(),
+            0.into(),
+            0.into(),
+            (1.0 / cell_height).into(),
+            0.into(),
+            0.into(),
+        ],
+        "Resources" => dictionary! {
+            "Font" => dictionary! { font::RESOURCE_NAME => font_id },
+        },
+    };
+    let mut stream = Stream::new(dict, Content { operations }.encode()?);
+    stream.compress()?;
+    Ok(stream)
+}
+
+/// Largest font size up to `size` at which `text` fits in `width`
+fn fit_size(font: &Font, text: &str, size: f32, width: f32) -> f32 {
+    let text_width = font.width(text, size);
+    if text_width <= width {
+        size
+    } else {
+        (size * width / text_width).max(PROXY_MIN_TEXT_SIZE)
+    }
+}
+
+/// Break text into lines no wider than `width`, as a list of lines for each
+/// paragraph
+fn wrap_text(font: &Font, text: &str, size: f32, width: f32) -> Vec<Vec<String>> {
+    text.lines()
+        .map(|paragraph| {
+            let mut lines = Vec::new();
+            let mut line = String::new();
+            for word in paragraph.split_whitespace() {
+                let candidate = if line.is_empty() {
+                    word.to_string()
+                } else {
+                    format!("{line} {word}")
+                };
+                if line.is_empty() || font.width(&candidate, size) <= width {
+                    line = candidate;
+                } else {
+                    lines.push(std::mem::replace(&mut line, word.to_string()));
+                }
+            }
+            lines.push(line);
+            lines
+        })
+        .collect()
+}
+
+/// Height of wrapped text drawn at `size`
+fn text_height(paragraphs: &[Vec<String>], size: f32) -> f32 {
+    let lines = paragraphs.iter().map(Vec::len).sum::<usize>();
+    let gaps = paragraphs.len().saturating_sub(1);
+    size * (lines as f32 * PROXY_LEADING + gaps as f32 * PROXY_PARAGRAPH_GAP)
+}
+
+/// Mana cost with braces removed from single symbols, e.g. `{2}{W/U}{U}`
+/// becomes `2(W/U)U`
+fn mana_symbols(cost: &str) -> String {
+    let mut symbols = String::new();
+    let mut rest = cost;
+    while let Some(start) = rest.find('{') {
+        let Some(len) = rest[start..].find('}') else {
+            break;
+        };
+        symbols.push_str(&rest[..start]);
+        let symbol = &rest[start + 1..start + len];
+        if symbol.chars().count() == 1 || symbol.chars().all(|c| c.is_ascii_digit()) {
+            symbols.push_str(symbol);
+        } else {
+            symbols.push_str(&format!("({symbol})"));
+        }
+        rest = &rest[start + len + 1..];
+    }
+    symbols.push_str(rest);
+    symbols
+}
+
+/// Fill the transparent (rounded) corners of a card image
+fn fill_corners(image: &mut RgbaImage, mode: CornerMode) {
+    let border = border_color(image);
+    let blend = |pixel: &mut Rgba<u8>, fill: Rgba<u8>| {
+        let Rgba([r, g, b, a]) = *pixel;
+        let mix =
+            |c: u8, f: u8| ((c as u16 * a as u16 + f as u16 * (0xff - a as u16)) / 0xff) as u8;
+        *pixel = Rgba([mix(r, fill[0]), mix(g, fill[1]), mix(b, fill[2]), 0xff]);
+    };
+    for row in image.rows_mut() {
+        let mut row = row.collect::<Vec<_>>();
+        let first = row.iter().position(|pixel| pixel[3] == 0xff);
+        let last = row.iter().rposition(|pixel| pixel[3] == 0xff);
+        let (left, right) = match (mode, first, last) {
+            (CornerMode::Square, Some(first), Some(last)) => (*row[first], *row[last]),
+            _ => (border, border),
+        };
+        let middle = row.len() / 2;
+        for (i, pixel) in row.iter_mut().enumerate() {
+            if pixel[3] != 0xff {
+                blend(pixel, if i < middle { left } else { right });
+            }
+        }
+    }
+}
+
+/// Detect the border color of a card image, from the median of opaque pixels
+/// just inside its edges (ignoring the corners)
+fn border_color(image: &RgbaImage) -> Rgba<u8> {
+    let (width, height) = image.dimensions();
+    let inset = width * BORDER_INSET / 1000;
+    let (x_skip, y_skip) = (width / 10, height / 10);
+    let mut samples = Vec::new();
+    for x in x_skip..width - x_skip {
+        samples.push(*image.get_pixel(x, inset));
+        samples.push(*image.get_pixel(x, height - 1 - inset));
+    }
+    for y in y_skip..height - y_skip {
+        samples.push(*image.get_pixel(inset, y));
+        samples.push(*image.get_pixel(width - 1 - inset, y));
+    }
+    samples.retain(|pixel| pixel[3] == 0xff);
+    if samples.is_empty() {
+        return Rgba([0x00, 0x00, 0x00, 0xff]);
+    }
+    let median = |channel: usize| {
+        let mut values = samples
+            .iter()
+            .map(|pixel| pixel[channel])
+            .collect::<Vec<_>>();
+        values.sort_unstable();
+        values[values.len() / 2]
+    };
+    Rgba([median(0), median(1), median(2), 0xff])
+}
+
+/// Replace the frame of a card image with white, keeping a thin outline of the
+/// border color at the edge of the card
+fn thin_border(image: &mut RgbaImage) {
+    let border = border_color(image);
+    let (width, height) = image.dimensions();
+    let frame = width * FRAME_WIDTH / 1000;
+    let outline = width * OUTLINE_WIDTH / 1000;
+    for (x, y, pixel) in image.enumerate_pixels_mut() {
+        let edge = x.min(y).min(width - 1 - x).min(height - 1 - y);
+        let in_frame = (0..3).all(|c| pixel[c].abs_diff(border[c]) <= FRAME_TOLERANCE);
+        if (outline..frame).contains(&edge) && in_frame {
+            *pixel = Rgba([0xff, 0xff, 0xff, pixel[3]]);
+        }
+    }
+}
+
+/// Crop the longer side of an image equally from both ends to `aspect`, its
+/// width divided by its height
+fn crop_to_aspect(image: DynamicImage, aspect: f32) -> DynamicImage {
+    let (width, height) = image.dimensions();
+    let cropped_width = (height as f32 * aspect).round() as u32;
+    let cropped_height = (width as f32 / aspect).round() as u32;
+    if cropped_width < width {
+        image.crop_imm((width - cropped_width) / 2, 0, cropped_width, height)
+    } else if cropped_height < height {
+        image.crop_imm(0, (height - cropped_height) / 2, width, cropped_height)
+    } else {
+        image
+    }
+}
+
+/// Extend the shorter side of an image equally at both ends to `aspect`, its
+/// width divided by its height, with the border color of the image
+fn pad_to_aspect(image: DynamicImage, aspect: f32) -> DynamicImage {
+    let (width, height) = image.dimensions();
+    let padded_width = (height as f32 * aspect).round() as u32;
+    let padded_height = (width as f32 / aspect).round() as u32;
+    let (padded_width, padded_height) = if padded_width > width {
+        (padded_width, height)
+    } else if padded_height > height {
+        (width, padded_height)
+    } else {
+        return image;
+    };
+    let image = image.into_rgba8();
+    let mut padded = RgbaImage::from_pixel(padded_width, padded_height, border_color(&image));
+    let x = (padded_width - width) / 2;
+    let y = (padded_height - height) / 2;
+    imageops::replace(&mut padded, &image, x as i64, y as i64);
+    DynamicImage::from(padded)
+}
+
+/// Synthesize bleed around a card image by extending or mirroring its edges
+fn add_bleed(image: &RgbaImage, bleed: u32, mode: BleedMode) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    // Coordinate in the source image to sample for a coordinate in the bleed
+    let source = |pos: i64, len: u32| {
+        let len = len as i64;
+        let pos = match mode {
+            BleedMode::Mirror if pos < 0 => -pos - 1,
+            BleedMode::Mirror if pos >= len => 2 * len - pos - 1,
+            _ => pos,
+        };
+        pos.clamp(0, len - 1) as u32
+    };
+    RgbaImage::from_fn(width + 2 * bleed, height + 2 * bleed, |x, y| {
+        let x = source(x as i64 - bleed as i64, width);
+        let y = source(y as i64 - bleed as i64, height);
+        *image.get_pixel(x, y)
+    })
+}
+
+/// Decode a card image, checking its dimensions before decoding it
+fn decode_image(data: &[u8], card: Option<usize>) -> Result<DynamicImage, PrintError> {
+    let image_error = |err: ImageError| match err {
+        ImageError::Unsupported(_) => PrintError::UnsupportedFormat { card },
+        err => PrintError::Decode {
+            card,
+            message: err.to_string(),
+        },
+    };
+    let reader = || {
+        ImageReader::new(Cursor::new(data))
+            .with_guessed_format()
+            .map_err(|err| image_error(err.into()))
+    };
+    let format = reader()?.format();
+    if format.is_none() {
+        return Err(PrintError::UnsupportedFormat { card });
+    }
+    // Truncated jpegs decode without an error, with the missing part gray
+    if format == Some(ImageFormat::Jpeg) && !JpegHeader::is_complete(data) {
+        return Err(PrintError::Decode {
+            card,
+            message: "jpeg image is truncated".to_string(),
+        });
+    }
+    let (width, height) = reader()?.into_dimensions().map_err(image_error)?;
+    if width > MAX_IMAGE_SIZE || height > MAX_IMAGE_SIZE {
+        return Err(PrintError::OversizedImage {
+            card,
+            width,
+            height,
         });
-        doc.trailer.set("Root", catalog_id);
+    }
+    reader()?.decode().map_err(image_error)
+}
+
+/// Gray card crossed out from corner to corner, printed in place of cards
+/// whose image can't be used
+fn placeholder() -> DynamicImage {
+    let (width, height) = (WIDTH as f32, HEIGHT as f32);
+    let diagonal = width.hypot(height);
+    let image = RgbImage::from_fn(WIDTH, HEIGHT, |x, y| {
+        let (x, y) = (x as f32 + 0.5, y as f32 + 0.5);
+        let edge = x.min(y).min(width - x).min(height - y);
+        let down = (height * x - width * y).abs() / diagonal;
+        let up = (height * x + width * y - width * height).abs() / diagonal;
+        if edge.min(down).min(up) < PLACEHOLDER_LINE_WIDTH / 2.0 {
+            PLACEHOLDER_FOREGROUND
+        } else {
+            PLACEHOLDER_BACKGROUND
+        }
+    });
+    DynamicImage::from(image)
+}
+
+/// Header information of a jpeg that can be embedded directly in a pdf
+struct JpegHeader {
+    width: u32,
+    height: u32,
+    color_space: &'static str,
+}
+
+impl JpegHeader {
+    /// Parse the frame header of an 8 bit baseline or progressive jpeg with
+    /// grayscale or RGB components
+    fn parse(data: &[u8]) -> Option<Self> {
+        if !data.starts_with(&[0xff, 0xd8]) {
+            return None;
+        }
+        let mut pos = 2;
+        while pos + 4 <= data.len() {
+            if data[pos] != 0xff {
+                return None;
+            }
+            let marker = data[pos + 1];
+            match marker {
+                // Fill byte
+                0xff => pos += 1,
+                // Markers without a length
+                0x01 | 0xd0..=0xd9 => pos += 2,
+                // Start of frame
+                0xc0..=0xc2 => {
+                    let frame = data.get(pos + 4..pos + 10)?;
+                    let precision = frame[0];
+                    let height = u16::from_be_bytes([frame[1], frame[2]]) as u32;
+                    let width = u16::from_be_bytes([frame[3], frame[4]]) as u32;
+                    let color_space = match frame[5] {
+                        1 => "DeviceGray",
+                        3 => "DeviceRGB",
+                        _ => return None,
+                    };
+                    if precision != 8 || width == 0 || height == 0 || !Self::is_complete(data) {
+                        return None;
+                    }
+                    return Some(JpegHeader {
+                        width,
+                        height,
+                        color_space,
+                    });
+                }
+                // Other start of frame markers aren't supported by DCTDecode
+                0xc3 | 0xc5..=0xc7 | 0xc9..=0xcb | 0xcd..=0xcf => return None,
+                _ => {
+                    let len = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+                    pos += 2 + len;
+                }
+            }
+        }
+        None
+    }
 
-        // Write document to stream
-        let mut buffer = Vec::new();
-        doc.save_to(&mut buffer)?;
-        Ok(buffer)
+    /// Whether the scan data of a jpeg is followed by an end of image marker,
+    /// which is missing from truncated files
+    fn is_complete(data: &[u8]) -> bool {
+        let mut pos = 2;
+        while pos + 4 <= data.len() && data[pos] == 0xff {
+            match data[pos + 1] {
+                0xff => pos += 1,
+                0x01 | 0xd0..=0xd9 => pos += 2,
+                // Start of scan, after which markers in the entropy coded data
+                // are escaped, so the end of image marker can't appear by chance.
+                // Markers before it are skipped, since embedded thumbnails end
+                // with one too
+                0xda => return data[pos..].windows(2).any(|bytes| bytes == [0xff, 0xd9]),
+                _ => {
+                    let len = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+                    pos += 2 + len;
+                }
+            }
+        }
+        false
     }
 }
+
+/// Name of the image XObject for the card at index `idx`
+fn image_name(idx: usize) -> String {
+    format!("I{}", idx + 1)
+}
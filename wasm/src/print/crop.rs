@@ -0,0 +1,242 @@
+use image::{DynamicImage, GenericImageView, Rgba, RgbaImage, imageops::FilterType};
+use imageproc::{
+    contours::{BorderType, find_contours},
+    distance_transform::Norm,
+    edges::canny,
+    geometric_transformations::{Interpolation, Projection, warp_into},
+    geometry::min_area_rect,
+    morphology::dilate,
+    point::Point,
+};
+
+use super::{CARD_HEIGHT_MM, CARD_WIDTH_MM, crop_to_aspect};
+
+// Longest side of the downscaled copy of an image that edges are detected in,
+// in pixels
+const DETECT_SIZE: u32 = 512;
+// Gradient thresholds of the canny edge detector
+const EDGE_LOW: f32 = 20.0;
+const EDGE_HIGH: f32 = 60.0;
+// Smallest area of a detected card, as a fraction of the image area
+const MIN_CARD_AREA: f32 = 0.25;
+// Largest difference between the aspect ratio of a detected card and a real
+// card, as the shorter side divided by the longer one
+const ASPECT_TOLERANCE: f32 = 0.08;
+// Images whose aspect ratio is this close to a card are already cropped, and
+// the frame inside their border would be mistaken for the edge of the card
+const CROPPED_TOLERANCE: f32 = 0.01;
+// Distance the crop is moved inside the detected edges, in pixels of the
+// downscaled copy, so no background is left around the card
+const EDGE_INSET: f32 = 4.0;
+
+/// Find the card in a scanned or photographed image, then straighten the image
+/// and crop it to the card at its aspect ratio. Returns `None` if the image is
+/// already cropped, or no outline shaped like a card is found
+pub(super) fn auto_crop(image: &DynamicImage) -> Option<DynamicImage> {
+    let (width, height) = image.dimensions();
+    let aspect = width.min(height) as f32 / width.max(height) as f32;
+    if (aspect - CARD_WIDTH_MM / CARD_HEIGHT_MM).abs() <= CROPPED_TOLERANCE {
+        return None;
+    }
+    let [top_left, top_right, bottom_right, bottom_left] = detect_card(image)?;
+    let width = distance(top_left, top_right).round() as u32;
+    let height = distance(top_left, bottom_left).round() as u32;
+    if width == 0 || height == 0 {
+        return None;
+    }
+    let (width_f, height_f) = (width as f32, height as f32);
+    let projection = Projection::from_control_points(
+        [top_left, top_right, bottom_right, bottom_left],
+        [
+            (0.0, 0.0),
+            (width_f, 0.0),
+            (width_f, height_f),
+            (0.0, height_f),
+        ],
+    )?;
+    // Pixels outside the image (of cards cut off by the edge of a scan) are
+    // left transparent, and filled like rounded corners
+    let mut cropped = RgbaImage::new(width, height);
+    warp_into(
+        &image.to_rgba8(),
+        &projection,
+        Interpolation::Bilinear,
+        Rgba([0x00, 0x00, 0x00, 0x00]),
+        &mut cropped,
+    );
+    let aspect = if width > height {
+        CARD_HEIGHT_MM / CARD_WIDTH_MM
+    } else {
+        CARD_WIDTH_MM / CARD_HEIGHT_MM
+    };
+    Some(crop_to_aspect(DynamicImage::from(cropped), aspect))
+}
+
+/// Detect the corners of the card in an image, clockwise from the top left.
+/// The card is the largest rectangle around a connected outline of edges,
+/// with an aspect ratio close to that of a card
+fn detect_card(image: &DynamicImage) -> Option<[(f32, f32); 4]> {
+    let (width, height) = image.dimensions();
+    let gray = if width.max(height) > DETECT_SIZE {
+        image
+            .resize(DETECT_SIZE, DETECT_SIZE, FilterType::Triangle)
+            .to_luma8()
+    } else {
+        image.to_luma8()
+    };
+    let scale = width as f32 / gray.width() as f32;
+    // Thicken edges to close small gaps in the outline of the card
+    let edges = dilate(&canny(&gray, EDGE_LOW, EDGE_HIGH), Norm::LInf, 1);
+
+    let card_aspect = CARD_WIDTH_MM / CARD_HEIGHT_MM;
+    let min_area = MIN_CARD_AREA * (gray.width() * gray.height()) as f32;
+    let mut best: Option<([(f32, f32); 4], f32)> = None;
+    for contour in find_contours::<i32>(&edges) {
+        if contour.border_type != BorderType::Outer {
+            continue;
+        }
+        let corners = min_area_rect(&contour.points).map(|Point { x, y }| (x as f32, y as f32));
+        let (long, short) = {
+            let a = distance(corners[0], corners[1]);
+            let b = distance(corners[1], corners[2]);
+            (a.max(b), a.min(b))
+        };
+        let area = long * short;
+        if long == 0.0 || area < min_area || (short / long - card_aspect).abs() > ASPECT_TOLERANCE {
+            continue;
+        }
+        if best.is_none_or(|(_, best_area)| area > best_area) {
+            best = Some((corners, area));
+        }
+    }
+
+    let (corners, _) = best?;
+    let corners = sort_corners(corners);
+    Some(inset(corners, EDGE_INSET).map(|(x, y)| (x * scale, y * scale)))
+}
+
+/// Order the corners of a rectangle clockwise from the top left, which is the
+/// corner nearest the origin
+fn sort_corners(mut corners: [(f32, f32); 4]) -> [(f32, f32); 4] {
+    let center_x = corners.iter().map(|&(x, _)| x).sum::<f32>() / 4.0;
+    let center_y = corners.iter().map(|&(_, y)| y).sum::<f32>() / 4.0;
+    // Angles increase clockwise, since y points down
+    corners.sort_by(|a, b| {
+        let angle = |(x, y): (f32, f32)| (y - center_y).atan2(x - center_x);
+        angle(*a).total_cmp(&angle(*b))
+    });
+    let top_left = (0..4)
+        .min_by(|&a, &b| {
+            let sum = |(x, y): (f32, f32)| x + y;
+            sum(corners[a]).total_cmp(&sum(corners[b]))
+        })
+        .unwrap_or(0);
+    corners.rotate_left(top_left);
+    corners
+}
+
+/// Move the corners of a rectangle, clockwise from the top left, `amount`
+/// inwards from each edge
+fn inset(corners: [(f32, f32); 4], amount: f32) -> [(f32, f32); 4] {
+    let unit = |from: (f32, f32), to: (f32, f32)| {
+        let length = distance(from, to).max(f32::EPSILON);
+        ((to.0 - from.0) / length, (to.1 - from.1) / length)
+    };
+    let across = unit(corners[0], corners[1]);
+    let down = unit(corners[0], corners[3]);
+    let signs = [(1.0, 1.0), (-1.0, 1.0), (-1.0, -1.0), (1.0, -1.0)];
+    let mut inset = corners;
+    for ((x, y), (across_sign, down_sign)) in inset.iter_mut().zip(signs) {
+        *x += amount * (across_sign * across.0 + down_sign * down.0);
+        *y += amount * (across_sign * across.1 + down_sign * down.1);
+    }
+    inset
+}
+
+fn distance(a: (f32, f32), b: (f32, f32)) -> f32 {
+    (b.0 - a.0).hypot(b.1 - a.1)
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{DynamicImage, GenericImageView, Rgb, RgbImage};
+
+    use super::{auto_crop, inset, sort_corners};
+
+    /// Card of `width` by `height` pixels centered on a light gray scanner bed
+    fn scan(bed: (u32, u32), width: u32, height: u32) -> DynamicImage {
+        let (left, top) = ((bed.0 - width) / 2, (bed.1 - height) / 2);
+        let image = RgbImage::from_fn(bed.0, bed.1, |x, y| {
+            if (left..left + width).contains(&x) && (top..top + height).contains(&y) {
+                Rgb([0x20, 0x40, 0x80])
+            } else {
+                Rgb([0xe0, 0xe0, 0xe0])
+            }
+        });
+        DynamicImage::from(image)
+    }
+
+    fn assert_corners(actual: [(f32, f32); 4], expected: [(f32, f32); 4]) {
+        let close =
+            |a: (f32, f32), b: (f32, f32)| (a.0 - b.0).abs() < 1e-3 && (a.1 - b.1).abs() < 1e-3;
+        assert!(
+            actual.iter().zip(expected).all(|(&a, b)| close(a, b)),
+            "expected {expected:?}, got {actual:?}"
+        );
+    }
+
+    #[test]
+    fn corners_are_sorted_clockwise_from_the_top_left() {
+        let rectangle = [(0.0, 0.0), (63.0, 0.0), (63.0, 88.0), (0.0, 88.0)];
+        let shuffled = [rectangle[2], rectangle[0], rectangle[3], rectangle[1]];
+        assert_corners(sort_corners(shuffled), rectangle);
+
+        // A slightly tilted rectangle, with the top left corner nearest the
+        // origin even though another corner is higher up
+        let tilted = [(10.0, 4.0), (70.0, 0.0), (76.0, 90.0), (16.0, 94.0)];
+        let shuffled = [tilted[3], tilted[1], tilted[0], tilted[2]];
+        assert_corners(sort_corners(shuffled), tilted);
+    }
+
+    #[test]
+    fn inset_moves_corners_inside_each_edge() {
+        let rectangle = [(0.0, 0.0), (60.0, 0.0), (60.0, 90.0), (0.0, 90.0)];
+        assert_corners(
+            inset(rectangle, 2.0),
+            [(2.0, 2.0), (58.0, 2.0), (58.0, 88.0), (2.0, 88.0)],
+        );
+
+        // Corners of a rectangle turned by 90 degrees move along its own edges
+        let turned = [(90.0, 0.0), (90.0, 60.0), (0.0, 60.0), (0.0, 0.0)];
+        assert_corners(
+            inset(turned, 2.0),
+            [(88.0, 2.0), (88.0, 58.0), (2.0, 58.0), (2.0, 2.0)],
+        );
+    }
+
+    #[test]
+    fn cards_are_cropped_in_their_orientation() {
+        let card = auto_crop(&scan((800, 1100), 630, 880)).unwrap();
+        let (width, height) = card.dimensions();
+        assert!(height > width);
+        assert!((width as f32 / height as f32 - 63.0 / 88.0).abs() < 0.01);
+
+        // Landscape cards (e.g. planes) stay landscape
+        let card = auto_crop(&scan((1100, 800), 880, 630)).unwrap();
+        let (width, height) = card.dimensions();
+        assert!(width > height);
+        assert!((height as f32 / width as f32 - 63.0 / 88.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn cropped_images_are_left_alone() {
+        // The frame inside the border of a cropped card isn't mistaken for
+        // its edge, even at a slightly different aspect ratio
+        assert!(auto_crop(&scan((630, 880), 540, 754)).is_none());
+        assert!(auto_crop(&scan((630, 884), 540, 754)).is_none());
+        assert!(auto_crop(&scan((884, 630), 754, 540)).is_none());
+
+        // Images further from the aspect ratio of a card are searched
+        assert!(auto_crop(&scan((630, 950), 540, 754)).is_some());
+    }
+}
@@ -0,0 +1,842 @@
+use std::{
+    collections::{HashMap, hash_map::Entry},
+    fmt::Write as _,
+    io::{Cursor, Write},
+    str::FromStr,
+};
+
+use anyhow::{Result, bail};
+use image::{
+    DynamicImage, GrayAlphaImage, GrayImage, Luma, Rgb, RgbImage, Rgba, RgbaImage,
+    codecs::jpeg::{JpegEncoder, PixelDensity},
+    imageops::{self, FilterType},
+};
+use imageproc::geometric_transformations::{Interpolation, Projection, warp_into};
+use tiff::{
+    encoder::{Rational, TiffEncoder, colortype},
+    tags::ResolutionUnit,
+};
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use super::{
+    CardFace, CardImage, CardSize, ImageOptions, LINE_ART_THRESHOLD, POINTS_PER_MM, PrintJob,
+    RenderMode, STAMP_GRAY, Section, Shape, Stamp, decode_image, placeholder,
+};
+use crate::{
+    error::PrintError,
+    font::Font,
+    progress::{Phase, Progress},
+    zip::ZipWriter,
+};
+
+/// Format of the output of a print job. Every format other than pdf is a zip
+/// of image or svg files
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Pdf,
+    /// Png of each card including its bleed, for print services that take
+    /// one image per card
+    CardImages,
+    /// Png of each page
+    PngSheets,
+    /// Jpeg of each page, much smaller than png for photographic card art
+    JpegSheets,
+    /// Uncompressed tiff of each page
+    TiffSheets,
+    /// Svg of each page, with embedded card images and vector cut paths
+    SvgSheets,
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "pdf" => Ok(OutputFormat::Pdf),
+            "images" => Ok(OutputFormat::CardImages),
+            "png" => Ok(OutputFormat::PngSheets),
+            "jpeg" | "jpg" => Ok(OutputFormat::JpegSheets),
+            "tiff" => Ok(OutputFormat::TiffSheets),
+            "svg" => Ok(OutputFormat::SvgSheets),
+            _ => bail!("unknown output format {s}"),
+        }
+    }
+}
+
+// Quality of jpeg sheets, high enough that text on cards stays crisp
+const JPEG_QUALITY: u8 = 92;
+// Stroke width in points of lines drawn with the thinnest width in pdfs
+const HAIRLINE: f32 = 0.25;
+// Cut paths in svg sheets follow the rounded corners of cards
+const CORNER_RADIUS_MM: f32 = 3.175;
+const CUT_PATH_COLOR: &str = "#ff0000";
+// Characters that can't be used in file names on common systems
+const RESERVED_CHARS: &[char] = &['/', '\\', ':', '*', '?', '"', '<', '>', '|'];
+// Rows sampled in each row of pixels when filling the outlines of text
+const TEXT_SAMPLES: u32 = 4;
+
+/// Stamp drawn into the pixels of card images, since there is no page to
+/// draw it over the cards on
+struct RasterStamp {
+    shape: StampShape,
+    // Natural size of the stamp, which is fitted in its box on each card
+    size: (f32, f32),
+}
+
+enum StampShape {
+    /// Outlines of the text centered on the origin, with y going up like in
+    /// pdfs
+    Text(Vec<Vec<(f32, f32)>>),
+    /// Logo in the render mode of the job
+    Logo(RgbaImage),
+}
+
+impl ImageOptions {
+    /// Decode a card image and prepare it for image outputs, replacing it with
+    /// a placeholder if it can't be used and bad cards are skipped. Also
+    /// returns the width of its bleed in pixels
+    fn load_card(
+        &self,
+        image: &CardImage,
+    ) -> Result<(RgbaImage, u32, Option<PrintError>), PrintError> {
+        let data = match &image.face {
+            CardFace::Image(data) => data,
+            CardFace::Text(_) => {
+                return Err(PrintError::UnsupportedOutput(
+                    "text proxies can only be printed as pdf".to_string(),
+                ));
+            }
+        };
+        let (decoded, skipped) = match decode_image(data, image.card) {
+            Ok(decoded) => (image.adjustments.apply(decoded), None),
+            Err(err) if self.skip_bad_cards && err.is_card_error() => (placeholder(), Some(err)),
+            Err(err) => return Err(err),
+        };
+        let (prepared, bleed) = self.prepare(decoded, image.size);
+        Ok((self.render(prepared), bleed, skipped))
+    }
+
+    /// Convert an image to the render mode of the job
+    fn render(&self, image: DynamicImage) -> RgbaImage {
+        match self.render_mode {
+            RenderMode::Color => image.into_rgba8(),
+            RenderMode::Grayscale => DynamicImage::from(image.into_luma_alpha8()).into_rgba8(),
+            RenderMode::LineArt => {
+                let mut gray: GrayAlphaImage = image.into_luma_alpha8();
+                for pixel in gray.pixels_mut() {
+                    pixel[0] = if pixel[0] >= LINE_ART_THRESHOLD {
+                        0xff
+                    } else {
+                        0x00
+                    };
+                }
+                DynamicImage::from(gray).into_rgba8()
+            }
+        }
+    }
+}
+
+impl PrintJob {
+    /// Write a zip of the images or svgs of the output format to `writer`
+    pub(super) fn create_export<W: Write>(
+        &mut self,
+        images: &[CardImage],
+        pages: &[Vec<Section>],
+        writer: W,
+    ) -> Result<W, PrintError> {
+        if !self.color.is_rgb() {
+            return Err(PrintError::UnsupportedOutput(
+                "cmyk output can only be printed as pdf".to_string(),
+            ));
+        }
+        let stamp = self.raster_stamp()?;
+        let mut zip = ZipWriter::new(writer);
+        match self.output {
+            OutputFormat::CardImages => {
+                self.write_card_images(&mut zip, images, pages, stamp.as_ref())?
+            }
+            _ => self.write_sheets(&mut zip, images, pages, stamp.as_ref())?,
+        }
+        self.report_progress(Progress {
+            phase: Phase::Finishing,
+            current: 0,
+            total: 1,
+            bytes: zip.position(),
+        })?;
+        Ok(zip.finish()?)
+    }
+
+    /// The stamp of the job prepared for drawing into card images
+    fn raster_stamp(&self) -> Result<Option<RasterStamp>, PrintError> {
+        Ok(match &self.stamp {
+            Some(Stamp::Text(text)) => {
+                let font = Font::new()?;
+                let (width, height) = (font.width(text, 1.0), font.cap_height());
+                let outlines = font.outlines(text, 1.0, (-width / 2.0, -height / 2.0));
+                Some(RasterStamp {
+                    shape: StampShape::Text(outlines),
+                    size: (width, height),
+                })
+            }
+            Some(Stamp::Logo(logo)) => {
+                let width = logo.width() as f32 / logo.height() as f32;
+                let logo = self.image_options().render(logo.clone());
+                Some(RasterStamp {
+                    shape: StampShape::Logo(logo),
+                    size: (width, 1.0),
+                })
+            }
+            None => None,
+        })
+    }
+
+    /// Draw the stamp on a card image with `bleed` pixels of bleed around the
+    /// card, where pdfs draw it over the card
+    fn stamp_card(&self, card: &mut RgbaImage, bleed: u32, stamp: &RasterStamp) {
+        let (width, height) = stamp.size;
+        let ((x, y), (box_width, box_height)) = self.stamp_position.frame();
+        let (sin, cos) = self.stamp_rotation.to_radians().sin_cos();
+        let card_width = card.width().saturating_sub(2 * bleed) as f32;
+        let card_height = card.height().saturating_sub(2 * bleed) as f32;
+        let scale = (box_width * card_width / width).min(box_height * card_height / height);
+        let center = (
+            bleed as f32 + x * card_width,
+            bleed as f32 + (1.0 - y) * card_height,
+        );
+        // Pixel of a point of the stamp, flipped since pixels start at the top
+        let to_pixel = |(u, v): (f32, f32)| {
+            (
+                center.0 + scale * (cos * u - sin * v),
+                center.1 - scale * (sin * u + cos * v),
+            )
+        };
+
+        let layer = match &stamp.shape {
+            StampShape::Text(outlines) => {
+                let outlines: Vec<Vec<_>> = (outlines.iter())
+                    .map(|polygon| polygon.iter().copied().map(to_pixel).collect())
+                    .collect();
+                let coverage = fill_polygons(&outlines, card.width(), card.height());
+                let gray = (STAMP_GRAY * 255.0).round() as u8;
+                RgbaImage::from_fn(card.width(), card.height(), |x, y| {
+                    Rgba([gray, gray, gray, coverage.get_pixel(x, y)[0]])
+                })
+            }
+            StampShape::Logo(logo) => {
+                // Pixels of the logo are mapped to the unit tall box centered
+                // on the origin it is drawn in
+                let (logo_width, logo_height) = (logo.width() as f32, logo.height() as f32);
+                let to_stamp = |(px, py): (f32, f32)| {
+                    ((px / logo_width - 0.5) * width, 0.5 - py / logo_height)
+                };
+                let origin = to_pixel(to_stamp((0.0, 0.0)));
+                let right = to_pixel(to_stamp((1.0, 0.0)));
+                let down = to_pixel(to_stamp((0.0, 1.0)));
+                let mut layer = RgbaImage::new(card.width(), card.height());
+                #[rustfmt::skip]
+                let matrix = [
+                    right.0 - origin.0, down.0 - origin.0, origin.0,
+                    right.1 - origin.1, down.1 - origin.1, origin.1,
+                    0.0, 0.0, 1.0,
+                ];
+                let Some(projection) = Projection::from_matrix(matrix) else {
+                    return;
+                };
+                warp_into(
+                    logo,
+                    &projection,
+                    Interpolation::Bilinear,
+                    Rgba([0; 4]),
+                    &mut layer,
+                );
+                layer
+            }
+        };
+        blend_over(card, &layer, self.stamp_opacity);
+    }
+
+    /// Write a png of each distinct card face, at the size of the card and
+    /// its bleed
+    fn write_card_images<W: Write>(
+        &mut self,
+        zip: &mut ZipWriter<W>,
+        images: &[CardImage],
+        pages: &[Vec<Section>],
+        stamp: Option<&RasterStamp>,
+    ) -> Result<(), PrintError> {
+        let mut used = Vec::new();
+        for slot in pages.iter().flatten().flat_map(|section| &section.slots) {
+            for idx in [Some(slot.front), slot.back].into_iter().flatten() {
+                if !used.contains(&idx) {
+                    used.push(idx);
+                }
+            }
+        }
+        used.sort_unstable();
+
+        // Black bleed is part of the image, since there is no page to draw it on
+        let black_bleed = self.black_bleed() / self.scale / POINTS_PER_MM;
+        let options = self.image_options();
+        let generated_bleed = options.bleed;
+        for (i, &idx) in used.iter().enumerate() {
+            let image = &images[idx];
+            let (mut card, card_bleed, skipped) = options.load_card(image)?;
+            self.skipped.extend(skipped);
+            if let (false, Some(stamp)) = (image.back, stamp) {
+                self.stamp_card(&mut card, card_bleed, stamp);
+            }
+
+            let (width, height) = image.size.dimensions();
+            let pixels = |mm: f32| (mm / 25.4 * self.dpi as f32).round() as u32;
+            let bleed = generated_bleed + black_bleed;
+            let mut output = RgbaImage::from_pixel(
+                pixels(width + 2.0 * bleed),
+                pixels(height + 2.0 * bleed),
+                image::Rgba([0, 0, 0, if black_bleed > 0.0 { 0xff } else { 0 }]),
+            );
+            let card = imageops::resize(
+                &card,
+                pixels(width + 2.0 * generated_bleed),
+                pixels(height + 2.0 * generated_bleed),
+                self.sheet_filter(),
+            );
+            let offset_x = ((output.width() - card.width()) / 2) as i64;
+            let offset_y = ((output.height() - card.height()) / 2) as i64;
+            imageops::overlay(&mut output, &card, offset_x, offset_y);
+
+            let name = self.card_file_name(images, idx);
+            zip.add(&format!("{name}.png"), &self.encode_png(output)?)?;
+            self.report_progress(Progress {
+                phase: Phase::Images,
+                current: i as u32 + 1,
+                total: used.len() as u32,
+                bytes: zip.position(),
+            })?;
+        }
+        Ok(())
+    }
+
+    /// File name of a card face, numbered after its card
+    fn card_file_name(&self, images: &[CardImage], idx: usize) -> String {
+        let image = &images[idx];
+        let name = match image.card {
+            Some(card) => {
+                let name =
+                    (self.cards[card].name.clone()).unwrap_or_else(|| format!("Card {}", card + 1));
+                let side = if image.back { " (back)" } else { "" };
+                format!("{:03} {name}{side}", card + 1)
+            }
+            None if image.size == CardSize::Standard => "card back".to_string(),
+            None => format!("card back ({:?})", image.size).to_lowercase(),
+        };
+        name.replace(RESERVED_CHARS, "_")
+    }
+
+    /// Write an image or svg of the front (and back) of each page
+    fn write_sheets<W: Write>(
+        &mut self,
+        zip: &mut ZipWriter<W>,
+        images: &[CardImage],
+        pages: &[Vec<Section>],
+        stamp: Option<&RasterStamp>,
+    ) -> Result<(), PrintError> {
+        let double_sided = (pages.iter().flatten())
+            .flat_map(|section| &section.slots)
+            .any(|slot| slot.back.is_some());
+
+        // Images are kept until the last page that uses them
+        let mut last_use = HashMap::new();
+        for (i, sections) in pages.iter().enumerate() {
+            for slot in sections.iter().flat_map(|section| &section.slots) {
+                for idx in [Some(slot.front), slot.back].into_iter().flatten() {
+                    last_use.insert(idx, i);
+                }
+            }
+        }
+        let mut loaded = HashMap::<usize, RgbaImage>::new();
+        let options = self.image_options();
+        let image_count = last_use.len() as u32;
+        let mut images_loaded = 0;
+
+        for (i, sections) in pages.iter().enumerate() {
+            let mut sides = vec![("", self.page_shapes(sections))];
+            if double_sided {
+                sides.push(("-back", self.back_page_shapes(sections)));
+            }
+            for (suffix, shapes) in sides {
+                for shape in &shapes {
+                    let &Shape::Image { idx, .. } = shape else {
+                        continue;
+                    };
+                    if let Entry::Vacant(entry) = loaded.entry(idx) {
+                        let (mut image, bleed, skipped) = options.load_card(&images[idx])?;
+                        self.skipped.extend(skipped);
+                        if let (false, Some(stamp)) = (images[idx].back, stamp) {
+                            self.stamp_card(&mut image, bleed, stamp);
+                        }
+                        entry.insert(image);
+                        images_loaded += 1;
+                        self.report_progress(Progress {
+                            phase: Phase::Images,
+                            current: images_loaded,
+                            total: image_count,
+                            bytes: zip.position(),
+                        })?;
+                    }
+                }
+
+                let name = format!("page-{:03}{suffix}", i + 1);
+                if self.output == OutputFormat::SvgSheets {
+                    let cut = suffix.is_empty().then_some(&sections[..]);
+                    let svg = self.render_svg(&shapes, &loaded, cut)?;
+                    zip.add(&format!("{name}.svg"), svg.as_bytes())?;
+                } else {
+                    let sheet = self.render_sheet(&shapes, &loaded);
+                    let (data, extension) = match self.output {
+                        OutputFormat::TiffSheets => (encode_tiff(&sheet, self.dpi)?, "tiff"),
+                        OutputFormat::JpegSheets => (self.encode_jpeg(&sheet)?, "jpg"),
+                        _ => (self.encode_png(DynamicImage::from(sheet))?, "png"),
+                    };
+                    zip.add(&format!("{name}.{extension}"), &data)?;
+                }
+            }
+            loaded.retain(|idx, _| last_use[idx] > i);
+
+            self.report_progress(Progress {
+                phase: Phase::Pages,
+                current: i as u32 + 1,
+                total: pages.len() as u32,
+                bytes: zip.position(),
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Draw the shapes of a page on a white image at the output resolution
+    fn render_sheet(&self, shapes: &[Shape], images: &HashMap<usize, RgbaImage>) -> RgbImage {
+        let (page_width, page_height) = self.page.dimensions();
+        let scale = self.dpi as f32 / 72.0;
+        let mut sheet = RgbImage::from_pixel(
+            (page_width * scale).round() as u32,
+            (page_height * scale).round() as u32,
+            Rgb([0xff; 3]),
+        );
+        // Pixel bounds of a rectangle in points, flipped to start at the top
+        let bounds = |x: f32, y: f32, w: f32, h: f32| {
+            let (x0, x1) = (x.min(x + w) * scale, x.max(x + w) * scale);
+            let (y0, y1) = (y.min(y + h), y.max(y + h));
+            let (y0, y1) = ((page_height - y1) * scale, (page_height - y0) * scale);
+            [x0, y0, x1, y1].map(|v| v.round().max(0.0) as u32)
+        };
+        let gray = |level: f32| Rgb([(level * 255.0).round().clamp(0.0, 255.0) as u8; 3]);
+
+        for shape in shapes {
+            match shape {
+                Shape::Fill { gray: level, rects } => {
+                    for &[x, y, w, h] in rects {
+                        fill_rect(&mut sheet, bounds(x, y, w, h), gray(*level));
+                    }
+                }
+                Shape::Stroke {
+                    gray: level,
+                    width,
+                    dash,
+                    paths,
+                    rects,
+                } => {
+                    // Lines on pages are horizontal or vertical, so each dash
+                    // is drawn as a rectangle
+                    let half = (stroke_width(*width) / 2.0).max(0.5 / scale);
+                    for [from, to] in lines(paths, rects) {
+                        for [(x0, y0), (x1, y1)] in dashes(from, to, *dash) {
+                            let (x, y) = (x0.min(x1) - half, y0.min(y1) - half);
+                            let (w, h) =
+                                ((x1 - x0).abs() + 2.0 * half, (y1 - y0).abs() + 2.0 * half);
+                            fill_rect(&mut sheet, bounds(x, y, w, h), gray(*level));
+                        }
+                    }
+                }
+                Shape::Image {
+                    idx,
+                    matrix: [a, d, e, f],
+                } => {
+                    let Some(image) = images.get(idx) else {
+                        continue;
+                    };
+                    let [x0, y0, x1, y1] = bounds(*e, *f, *a, *d);
+                    if x1 <= x0 || y1 <= y0 {
+                        continue;
+                    }
+                    let mut image = imageops::resize(image, x1 - x0, y1 - y0, self.sheet_filter());
+                    if *a < 0.0 {
+                        imageops::rotate180_in_place(&mut image);
+                    }
+                    blend(&mut sheet, &image, (x0, y0));
+                }
+            }
+        }
+        sheet
+    }
+
+    /// Svg of the shapes of a page. Cut paths are added around the cards of
+    /// `cut`, so front pages can be sent to a cutting machine as is
+    fn render_svg(
+        &self,
+        shapes: &[Shape],
+        images: &HashMap<usize, RgbaImage>,
+        cut: Option<&[Section]>,
+    ) -> Result<String> {
+        let (page_width, page_height) = self.page.dimensions();
+        let mut svg = String::new();
+        let _ = write!(
+            svg,
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <svg xmlns=\"http://www.w3.org/2000/svg\" \
+             xmlns:xlink=\"http://www.w3.org/1999/xlink\" \
+             width=\"{}mm\" height=\"{}mm\" viewBox=\"0 0 {page_width} {page_height}\">\n",
+            page_width / POINTS_PER_MM,
+            page_height / POINTS_PER_MM,
+        );
+        let gray = |level: f32| {
+            let value = (level * 255.0).round().clamp(0.0, 255.0) as u8;
+            format!("#{value:02x}{value:02x}{value:02x}")
+        };
+
+        // Each image is embedded once, in a unit square like in pdfs
+        let mut defined = Vec::new();
+        svg.push_str("<defs>\n");
+        for shape in shapes {
+            let &Shape::Image { idx, .. } = shape else {
+                continue;
+            };
+            if let (false, Some(image)) = (defined.contains(&idx), images.get(&idx)) {
+                let png = self.encode_png(DynamicImage::from(image.clone()))?;
+                let _ = writeln!(
+                    svg,
+                    "<image id=\"card-{idx}\" width=\"1\" height=\"1\" \
+                     preserveAspectRatio=\"none\" xlink:href=\"data:image/png;base64,{}\"/>",
+                    base64(&png),
+                );
+                defined.push(idx);
+            }
+        }
+        svg.push_str("</defs>\n");
+
+        for shape in shapes {
+            match shape {
+                Shape::Fill { gray: level, rects } => {
+                    for &[x, y, w, h] in rects {
+                        let _ = writeln!(
+                            svg,
+                            "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"/>",
+                            x.min(x + w),
+                            page_height - y.max(y + h),
+                            w.abs(),
+                            h.abs(),
+                            gray(*level),
+                        );
+                    }
+                }
+                Shape::Stroke {
+                    gray: level,
+                    width,
+                    dash,
+                    paths,
+                    rects,
+                } => {
+                    let mut path = String::new();
+                    for [(x0, y0), (x1, y1)] in lines(paths, rects) {
+                        let _ = write!(
+                            path,
+                            "M{x0} {} L{x1} {} ",
+                            page_height - y0,
+                            page_height - y1
+                        );
+                    }
+                    let dash = dash
+                        .map(|dash| format!(" stroke-dasharray=\"{dash}\""))
+                        .unwrap_or_default();
+                    let _ = writeln!(
+                        svg,
+                        "<path d=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"{}\"{dash}/>",
+                        path.trim_end(),
+                        gray(*level),
+                        stroke_width(*width),
+                    );
+                }
+                Shape::Image {
+                    idx,
+                    matrix: [a, d, e, f],
+                } => {
+                    // Flip the unit square so the top row of the image is at
+                    // the top of the card
+                    let _ = writeln!(
+                        svg,
+                        "<use xlink:href=\"#card-{idx}\" transform=\"matrix({a} 0 0 {d} {e} {})\"/>",
+                        page_height - d - f,
+                    );
+                }
+            }
+        }
+
+        if let Some(sections) = cut {
+            let radius = CORNER_RADIUS_MM * POINTS_PER_MM * self.scale;
+            let _ = writeln!(
+                svg,
+                "<g id=\"cut-paths\" fill=\"none\" stroke=\"{CUT_PATH_COLOR}\" stroke-width=\"{HAIRLINE}\">"
+            );
+            for section in sections {
+                let grid = section.grid;
+                for idx in 0..section.slots.len() {
+                    let (x, y) = grid.position(idx);
+                    let _ = writeln!(
+                        svg,
+                        "<rect x=\"{x}\" y=\"{}\" width=\"{}\" height=\"{}\" rx=\"{radius}\"/>",
+                        page_height - y - grid.card_height,
+                        grid.card_width,
+                        grid.card_height,
+                    );
+                }
+            }
+            svg.push_str("</g>\n");
+        }
+        svg.push_str("</svg>\n");
+        Ok(svg)
+    }
+
+    /// Filter images are scaled with when drawn on outputs, which always need
+    /// resampling to the output resolution
+    fn sheet_filter(&self) -> FilterType {
+        self.resample_filter
+            .filter_type()
+            .unwrap_or(FilterType::CatmullRom)
+    }
+
+    /// Png with the output resolution recorded, so print services see the
+    /// physical size of the image
+    fn encode_png(&self, image: impl Into<DynamicImage>) -> Result<Vec<u8>> {
+        let image = image.into();
+        let (width, height) = (image.width(), image.height());
+        let (color, pixels) = match image {
+            DynamicImage::ImageRgb8(image) => (png::ColorType::Rgb, image.into_raw()),
+            image => (png::ColorType::Rgba, image.into_rgba8().into_raw()),
+        };
+        let pixels_per_metre = (self.dpi as f32 / 0.0254).round() as u32;
+        let mut png = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png, width, height);
+        encoder.set_color(color);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_pixel_dims(Some(png::PixelDimensions {
+            xppu: pixels_per_metre,
+            yppu: pixels_per_metre,
+            unit: png::Unit::Meter,
+        }));
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&pixels)?;
+        writer.finish()?;
+        Ok(png)
+    }
+
+    /// Jpeg with the output resolution recorded
+    fn encode_jpeg(&self, image: &RgbImage) -> Result<Vec<u8>> {
+        let mut jpeg = Vec::new();
+        let mut encoder = JpegEncoder::new_with_quality(&mut jpeg, JPEG_QUALITY);
+        encoder.set_pixel_density(PixelDensity::dpi(self.dpi as u16));
+        encoder.encode_image(image)?;
+        Ok(jpeg)
+    }
+}
+
+/// Width of a stroke in points, drawing the thinnest lines of pdfs as hairlines
+fn stroke_width(width: f32) -> f32 {
+    if width > 0.0 { width } else { HAIRLINE }
+}
+
+/// Straight lines of the paths and rectangle outlines of a stroke
+fn lines(paths: &[Vec<(f32, f32)>], rects: &[[f32; 4]]) -> Vec<[(f32, f32); 2]> {
+    let mut lines = Vec::new();
+    for path in paths {
+        lines.extend(path.windows(2).map(|line| [line[0], line[1]]));
+    }
+    for &[x, y, w, h] in rects {
+        let corners = [(x, y), (x + w, y), (x + w, y + h), (x, y + h)];
+        for i in 0..4 {
+            lines.push([corners[i], corners[(i + 1) % 4]]);
+        }
+    }
+    lines
+}
+
+/// Split a line from `from` to `to` into dashes of length `dash`, with gaps of
+/// the same length
+fn dashes(from: (f32, f32), to: (f32, f32), dash: Option<f32>) -> Vec<[(f32, f32); 2]> {
+    let length = ((to.0 - from.0).powi(2) + (to.1 - from.1).powi(2)).sqrt();
+    let dash = match dash {
+        Some(dash) if dash > 0.0 && length > 0.0 => dash,
+        _ => return vec![[from, to]],
+    };
+    let point = |t: f32| {
+        let t = t.min(length) / length;
+        (from.0 + (to.0 - from.0) * t, from.1 + (to.1 - from.1) * t)
+    };
+    let mut dashes = Vec::new();
+    let mut start = 0.0;
+    while start < length {
+        dashes.push([point(start), point(start + dash)]);
+        start += 2.0 * dash;
+    }
+    dashes
+}
+
+/// Coverage of the pixels of a `width` by `height` image by closed polygons,
+/// filled with the nonzero winding rule
+fn fill_polygons(polygons: &[Vec<(f32, f32)>], width: u32, height: u32) -> GrayImage {
+    let edges: Vec<_> = (polygons.iter())
+        .flat_map(|polygon| polygon.iter().zip(polygon.iter().cycle().skip(1)))
+        .filter(|(from, to)| from.1 != to.1)
+        .collect();
+    let (top, bottom) = (edges.iter()).fold((f32::MAX, f32::MIN), |(top, bottom), (from, to)| {
+        (top.min(from.1).min(to.1), bottom.max(from.1).max(to.1))
+    });
+    let top = top.floor().max(0.0) as u32;
+    let bottom = (bottom.ceil().max(0.0) as u32).min(height);
+
+    let mut coverage = vec![0.0f32; width as usize];
+    let mut crossings = Vec::new();
+    let mut image = GrayImage::new(width, height);
+    for row in top..bottom {
+        coverage.fill(0.0);
+        for sample in 0..TEXT_SAMPLES {
+            let y = row as f32 + (sample as f32 + 0.5) / TEXT_SAMPLES as f32;
+            crossings.clear();
+            for &(&(x0, y0), &(x1, y1)) in &edges {
+                if (y0 <= y) != (y1 <= y) {
+                    let x = x0 + (y - y0) / (y1 - y0) * (x1 - x0);
+                    crossings.push((x, if y1 > y0 { 1 } else { -1 }));
+                }
+            }
+            crossings.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+            // Spans between crossings inside the outline cover each pixel by
+            // how much of it they overlap
+            let mut winding = 0;
+            for pair in crossings.windows(2) {
+                winding += pair[0].1;
+                if winding == 0 {
+                    continue;
+                }
+                let (start, end) = (pair[0].0.max(0.0), pair[1].0.min(width as f32));
+                let mut x = start.floor();
+                while x < end {
+                    let overlap = end.min(x + 1.0) - start.max(x);
+                    coverage[x as usize] += overlap / TEXT_SAMPLES as f32;
+                    x += 1.0;
+                }
+            }
+        }
+        for (x, &value) in coverage.iter().enumerate() {
+            let value = (value.min(1.0) * 255.0).round() as u8;
+            image.put_pixel(x as u32, row, Luma([value]));
+        }
+    }
+    image
+}
+
+/// Draw `layer` over an image of the same size, with its transparency
+/// scaled by `opacity`
+fn blend_over(image: &mut RgbaImage, layer: &RgbaImage, opacity: f32) {
+    for (pixel, &Rgba(over)) in image.pixels_mut().zip(layer.pixels()) {
+        let alpha = over[3] as f32 / 255.0 * opacity;
+        if alpha <= 0.0 {
+            continue;
+        }
+        let below = pixel[3] as f32 / 255.0 * (1.0 - alpha);
+        let total = alpha + below;
+        for c in 0..3 {
+            let value = (over[c] as f32 * alpha + pixel[c] as f32 * below) / total;
+            pixel[c] = value.round() as u8;
+        }
+        pixel[3] = (total * 255.0).round() as u8;
+    }
+}
+
+/// Fill the pixels from `[x0, y0]` up to `[x1, y1]`
+fn fill_rect(image: &mut RgbImage, [x0, y0, x1, y1]: [u32; 4], color: Rgb<u8>) {
+    for y in y0..y1.min(image.height()) {
+        for x in x0..x1.min(image.width()) {
+            image.put_pixel(x, y, color);
+        }
+    }
+}
+
+/// Draw `image` over `sheet` with its top left corner at `(x, y)`, blending
+/// transparent pixels
+fn blend(sheet: &mut RgbImage, image: &RgbaImage, (x, y): (u32, u32)) {
+    for (dx, dy, pixel) in image.enumerate_pixels() {
+        let (sx, sy) = (x + dx, y + dy);
+        if sx >= sheet.width() || sy >= sheet.height() {
+            continue;
+        }
+        let [r, g, b, a] = pixel.0;
+        let below = sheet.get_pixel_mut(sx, sy);
+        for (channel, value) in below.0.iter_mut().zip([r, g, b]) {
+            let mixed = (value as u32 * a as u32 + *channel as u32 * (255 - a as u32)) / 255;
+            *channel = mixed as u8;
+        }
+    }
+}
+
+/// Uncompressed rgb tiff with its resolution set to `dpi`
+fn encode_tiff(image: &RgbImage, dpi: u32) -> Result<Vec<u8>> {
+    let mut tiff = Cursor::new(Vec::new());
+    let mut encoder = TiffEncoder::new(&mut tiff)?;
+    let mut page = encoder.new_image::<colortype::RGB8>(image.width(), image.height())?;
+    page.resolution(ResolutionUnit::Inch, Rational { n: dpi, d: 1 });
+    page.write_data(image.as_raw())?;
+    Ok(tiff.into_inner())
+}
+
+/// Standard base64 encoding, for images embedded in svgs
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut output = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let bits = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                output.push(ALPHABET[(bits >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                output.push('=');
+            }
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::base64;
+
+    #[test]
+    fn base64_matches_rfc_4648_vectors() {
+        let vectors = [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ];
+        for (input, output) in vectors {
+            assert_eq!(base64(input.as_bytes()), output);
+        }
+        assert_eq!(base64(&[0xfb, 0xff, 0xbf]), "+/+/");
+    }
+}
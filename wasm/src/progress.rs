@@ -0,0 +1,81 @@
+use std::{
+    fmt::{self, Display},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use wasm_bindgen::prelude::wasm_bindgen;
+
+/// Stage of a print job
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// Decoding and compressing card images
+    Images,
+    /// Laying out pages
+    Pages,
+    /// Writing the end of the pdf
+    Finishing,
+}
+
+/// Progress of a print job, reported after each step
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    pub phase: Phase,
+    /// Number of steps completed in the current phase
+    pub current: u32,
+    /// Total number of steps in the current phase
+    pub total: u32,
+    /// Number of bytes of pdf written so far
+    pub bytes: usize,
+}
+
+impl Display for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (current, total) = (self.current, self.total);
+        match self.phase {
+            Phase::Images => write!(f, "Processing images ({current} / {total})"),
+            Phase::Pages => write!(f, "Generating pages ({current} / {total})"),
+            Phase::Finishing => write!(f, "Finishing pdf"),
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl Progress {
+    /// Human readable description of the progress
+    #[wasm_bindgen]
+    pub fn message(&self) -> String {
+        self.to_string()
+    }
+}
+
+/// Handle used to cancel a running print job. The job checks the token
+/// between steps, so cancelling from a progress callback stops the job before
+/// the next image or page
+#[wasm_bindgen]
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+#[wasm_bindgen]
+impl CancelToken {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        CancelToken::default()
+    }
+
+    #[wasm_bindgen]
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    #[wasm_bindgen]
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
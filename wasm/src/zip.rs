@@ -0,0 +1,158 @@
+use anyhow::{Result, bail};
+use std::io::Write;
+
+// Signatures of zip records
+const LOCAL_HEADER: u32 = 0x04034b50;
+const CENTRAL_HEADER: u32 = 0x02014b50;
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x06054b50;
+// Version 2.0, the minimum for files in directories
+const VERSION: u16 = 20;
+// File names are utf-8
+const UTF8_FLAG: u16 = 1 << 11;
+
+/// Minimal zip writer that stores files without compression, since card images
+/// are already compressed. Each file is written to the output as soon as it is
+/// added
+pub struct ZipWriter<W: Write> {
+    inner: W,
+    // Number of bytes written so far
+    position: usize,
+    // Central directory entries of the files written so far
+    central: Vec<u8>,
+    count: u16,
+}
+
+impl<W: Write> ZipWriter<W> {
+    pub fn new(inner: W) -> Self {
+        ZipWriter {
+            inner,
+            position: 0,
+            central: Vec::new(),
+            count: 0,
+        }
+    }
+
+    /// Number of bytes written so far
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Write a file named `name`
+    pub fn add(&mut self, name: &str, data: &[u8]) -> Result<()> {
+        if data.len() > u32::MAX as usize || self.position > u32::MAX as usize {
+            bail!("zip file is too large");
+        }
+        if self.count == u16::MAX {
+            bail!("too many files in zip");
+        }
+        let crc = crc32(data);
+        let size = data.len() as u32;
+        let offset = self.position as u32;
+
+        let mut header = Vec::new();
+        header.extend(LOCAL_HEADER.to_le_bytes());
+        header.extend(VERSION.to_le_bytes());
+        write_file_info(&mut header, name, crc, size);
+        header.extend(name.as_bytes());
+        self.write_bytes(&header)?;
+        self.write_bytes(data)?;
+
+        self.central.extend(CENTRAL_HEADER.to_le_bytes());
+        // Made by and needed to extract
+        self.central.extend(VERSION.to_le_bytes());
+        self.central.extend(VERSION.to_le_bytes());
+        write_file_info(&mut self.central, name, crc, size);
+        // Comment length, disk number, internal and external attributes
+        self.central.extend([0; 10]);
+        self.central.extend(offset.to_le_bytes());
+        self.central.extend(name.as_bytes());
+        self.count += 1;
+        Ok(())
+    }
+
+    /// Write the central directory, and return the output
+    pub fn finish(mut self) -> Result<W> {
+        // Offsets in the end record are 32 bits, like in file headers
+        let (Ok(start), Ok(central_size)) = (
+            u32::try_from(self.position),
+            u32::try_from(self.central.len()),
+        ) else {
+            bail!("zip file is too large");
+        };
+        let central = std::mem::take(&mut self.central);
+        self.write_bytes(&central)?;
+
+        let mut end = Vec::new();
+        end.extend(END_OF_CENTRAL_DIRECTORY.to_le_bytes());
+        // Disk numbers
+        end.extend([0; 4]);
+        end.extend(self.count.to_le_bytes());
+        end.extend(self.count.to_le_bytes());
+        end.extend(central_size.to_le_bytes());
+        end.extend(start.to_le_bytes());
+        // Comment length
+        end.extend([0; 2]);
+        self.write_bytes(&end)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<()> {
+        self.inner.write_all(bytes)?;
+        self.position += bytes.len();
+        Ok(())
+    }
+}
+
+/// Fields shared by local and central headers, from the flags to the extra
+/// field length
+fn write_file_info(buffer: &mut Vec<u8>, name: &str, crc: u32, size: u32) {
+    buffer.extend(UTF8_FLAG.to_le_bytes());
+    // Stored without compression, at midnight on 1 January 1980
+    buffer.extend(0u16.to_le_bytes());
+    buffer.extend(0u16.to_le_bytes());
+    buffer.extend((1u16 << 5 | 1).to_le_bytes());
+    buffer.extend(crc.to_le_bytes());
+    buffer.extend(size.to_le_bytes());
+    buffer.extend(size.to_le_bytes());
+    buffer.extend((name.len() as u16).to_le_bytes());
+    buffer.extend(0u16.to_le_bytes());
+}
+
+/// Crc-32 checksum of the data of each file
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                crc >> 1 ^ 0xedb88320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ZipWriter;
+
+    #[test]
+    fn archives_past_4_gib_are_rejected() {
+        let mut zip = ZipWriter::new(Vec::new());
+        zip.add("card.png", b"png").unwrap();
+        zip.position = u32::MAX as usize + 1;
+        assert!(zip.add("card.png", b"png").is_err());
+        assert!(zip.finish().is_err());
+
+        // The last file can end past 4 GiB, leaving only the central
+        // directory out of range
+        let mut zip = ZipWriter::new(Vec::new());
+        zip.add("card.png", b"png").unwrap();
+        zip.position = u32::MAX as usize - 1;
+        zip.add("card.png", b"png").unwrap();
+        assert!(zip.finish().is_err());
+    }
+}
@@ -0,0 +1,1547 @@
+use image::{DynamicImage, ImageFormat, Rgb, RgbImage, Rgba, RgbaImage};
+use lopdf::{Document, Object, Stream, content::Content};
+use moxcms::{
+    ColorProfile, DataColorSpace, LutDataType, LutStore, LutType, LutWarehouse, Matrix3d,
+    ProfileClass, Xyzd,
+};
+use mtg_print::{
+    adjust::Adjustments,
+    color::ColorMode,
+    error::PrintError,
+    print::{
+        BleedMode, CardSize, CornerMode, CutGuides, Duplex, FitMode, Orientation, OutputFormat,
+        PaperSize, PrintJob, RenderMode, ResampleFilter, StampPosition, TextCard,
+    },
+    progress::{Phase, Progress},
+};
+use std::{cell::RefCell, collections::HashSet, io::Cursor, rc::Rc};
+
+// Physical size of a card in points
+const CARD_WIDTH: f32 = 63.0 * 72.0 / 25.4;
+const CARD_HEIGHT: f32 = 88.0 * 72.0 / 25.4;
+// Dimensions of letter paper in points
+const LETTER_WIDTH: f32 = 612.0;
+const LETTER_HEIGHT: f32 = 792.0;
+
+fn encode(image: impl Into<DynamicImage>, format: ImageFormat) -> Box<[u8]> {
+    let mut buffer = Vec::new();
+    image
+        .into()
+        .write_to(&mut Cursor::new(&mut buffer), format)
+        .unwrap();
+    buffer.into()
+}
+
+fn card_png(color: [u8; 4]) -> Box<[u8]> {
+    encode(
+        RgbaImage::from_pixel(745, 1040, Rgba(color)),
+        ImageFormat::Png,
+    )
+}
+
+fn card_jpg() -> Box<[u8]> {
+    let image = RgbImage::from_pixel(745, 1040, Rgb([0x20, 0x40, 0x80]));
+    encode(image, ImageFormat::Jpeg)
+}
+
+fn assert_close(actual: f32, expected: f32) {
+    assert!(
+        (actual - expected).abs() < 0.01,
+        "expected {expected}, got {actual}"
+    );
+}
+
+fn to_f32(object: &Object) -> f32 {
+    object.as_float().unwrap()
+}
+
+/// Transformation matrix and image name of a drawn card image
+struct Placement {
+    matrix: [f32; 6],
+    image: Vec<u8>,
+}
+
+struct Page {
+    size: (f32, f32),
+    placements: Vec<Placement>,
+}
+
+/// Returns the image placements on each page
+fn pages(doc: &Document) -> Vec<Page> {
+    let pages_id = doc.catalog().unwrap().get(b"Pages").unwrap();
+    let pages = doc
+        .get_dictionary(pages_id.as_reference().unwrap())
+        .unwrap();
+    let media_box = pages.get(b"MediaBox").unwrap().as_array().unwrap();
+    let size = (to_f32(&media_box[2]), to_f32(&media_box[3]));
+
+    let mut output = Vec::new();
+    for page_id in doc.page_iter() {
+        let content = Content::decode(&doc.get_page_content(page_id).unwrap()).unwrap();
+        let mut placements = Vec::new();
+        let mut matrix = None;
+        for op in content.operations {
+            match op.operator.as_str() {
+                "cm" => matrix = Some(std::array::from_fn(|i| to_f32(&op.operands[i]))),
+                "Do" => placements.push(Placement {
+                    matrix: matrix.take().unwrap(),
+                    image: op.operands[0].as_name().unwrap().to_vec(),
+                }),
+                _ => {}
+            }
+        }
+        output.push(Page { size, placements });
+    }
+    output
+}
+
+/// Card images in the document, excluding soft masks
+fn images(doc: &Document) -> Vec<&Stream> {
+    let is_image = |stream: &&Stream| {
+        matches!(
+            stream.dict.get(b"Subtype").and_then(Object::as_name),
+            Ok(b"Image")
+        )
+    };
+    let masks = doc
+        .objects
+        .values()
+        .filter_map(|object| object.as_stream().ok())
+        .filter_map(|stream| {
+            stream
+                .dict
+                .get(b"SMask")
+                .and_then(Object::as_reference)
+                .ok()
+        })
+        .collect::<HashSet<_>>();
+    doc.objects
+        .iter()
+        .filter(|(id, _)| !masks.contains(id))
+        .filter_map(|(_, object)| object.as_stream().ok())
+        .filter(is_image)
+        .collect()
+}
+
+/// Decompressed pixel data of a flate encoded image
+fn pixels(image: &Stream) -> Vec<u8> {
+    // lopdf refuses to decompress image streams directly
+    let mut stream = image.clone();
+    stream.dict.remove(b"Subtype");
+    stream.decompressed_content().unwrap()
+}
+
+#[test]
+fn cards_are_placed_at_physical_size() {
+    let mut job = PrintJob::new();
+    job.add_card(9, card_png([0x20, 0x40, 0x80, 0xff]));
+    let doc = Document::load_mem(&job.print().unwrap()).unwrap();
+
+    let pages = pages(&doc);
+    assert_eq!(pages.len(), 1);
+    assert_eq!(pages[0].size, (LETTER_WIDTH, LETTER_HEIGHT));
+    assert_eq!(pages[0].placements.len(), 9);
+
+    let left = (LETTER_WIDTH - 3.0 * CARD_WIDTH) / 2.0;
+    let top = (LETTER_HEIGHT + 3.0 * CARD_HEIGHT) / 2.0;
+    for (idx, placement) in pages[0].placements.iter().enumerate() {
+        let (i, j) = (idx % 3, idx / 3);
+        let matrix = placement.matrix;
+        assert_close(matrix[0], CARD_WIDTH);
+        assert_close(matrix[1], 0.0);
+        assert_close(matrix[2], 0.0);
+        assert_close(matrix[3], CARD_HEIGHT);
+        assert_close(matrix[4], left + i as f32 * CARD_WIDTH);
+        assert_close(matrix[5], top - (j + 1) as f32 * CARD_HEIGHT);
+    }
+}
+
+#[test]
+fn scale_correction_is_applied() {
+    let mut job = PrintJob::new();
+    job.add_card(1, card_png([0x20, 0x40, 0x80, 0xff]));
+    job.set_scale(0.995).unwrap();
+    let doc = Document::load_mem(&job.print().unwrap()).unwrap();
+
+    let matrix = pages(&doc)[0].placements[0].matrix;
+    assert_close(matrix[0], CARD_WIDTH * 0.995);
+    assert_close(matrix[3], CARD_HEIGHT * 0.995);
+}
+
+#[test]
+fn page_size_drives_media_box_and_grid() {
+    let mut job = PrintJob::new();
+    job.add_card(9, card_png([0x20, 0x40, 0x80, 0xff]));
+    job.set_paper_size(PaperSize::A4);
+    job.set_orientation(Orientation::Landscape);
+    let doc = Document::load_mem(&job.print().unwrap()).unwrap();
+
+    // 4 columns and 2 rows of cards fit on landscape A4
+    let pages = pages(&doc);
+    assert_eq!(pages.len(), 2);
+    assert_close(pages[0].size.0, 841.89);
+    assert_close(pages[0].size.1, 595.28);
+    assert_eq!(pages[0].placements.len(), 8);
+    assert_eq!(pages[1].placements.len(), 1);
+}
+
+#[test]
+fn pages_exactly_as_tall_as_their_rows_are_filled() {
+    // Pages fit exactly 1 and 4 rows of cards and their gutters between the
+    // cut guide margins, where rounding can put the space left on a new page
+    // just below the rows
+    for (scale, gutter_mm, rows) in [(0.941, 7.2, 1), (1.08, 6.3, 4)] {
+        let points_per_mm = 72.0f32 / 25.4;
+        let gutter = gutter_mm * points_per_mm * scale;
+        let pitch = 88.0 * points_per_mm * scale + gutter;
+        let height = 2.0 * 9.6 + rows as f32 * pitch - gutter;
+
+        let mut job = PrintJob::new();
+        job.add_card(3 * rows, card_png([0x20, 0x40, 0x80, 0xff]));
+        job.set_custom_paper_size(250.0, height).unwrap();
+        job.set_scale(scale).unwrap();
+        job.set_gutter(gutter_mm).unwrap();
+        let doc = Document::load_mem(&job.print().unwrap()).unwrap();
+        let pages = pages(&doc);
+        assert_eq!(pages.len(), 3);
+        assert!(
+            pages
+                .iter()
+                .all(|page| page.placements.len() == rows as usize)
+        );
+    }
+}
+
+#[test]
+fn legal_and_custom_paper_sizes_are_portrait_by_default() {
+    let mut job = PrintJob::new();
+    job.add_card(12, card_png([0x20, 0x40, 0x80, 0xff]));
+    job.set_paper_size(PaperSize::Legal);
+    let doc = Document::load_mem(&job.print().unwrap()).unwrap();
+    let page = &pages(&doc)[0];
+    assert_eq!(page.size, (612.0, 1008.0));
+    assert_eq!(page.placements.len(), 9);
+
+    // Custom sizes are portrait whichever side is given first, and 4 rows of
+    // cards fit on a 15 inch page
+    job.set_custom_paper_size(1080.0, 720.0).unwrap();
+    let doc = Document::load_mem(&job.print().unwrap()).unwrap();
+    let pages = pages(&doc);
+    assert_eq!(pages.len(), 1);
+    assert_eq!(pages[0].size, (720.0, 1080.0));
+    assert_eq!(pages[0].placements.len(), 12);
+}
+
+#[test]
+fn invalid_custom_paper_sizes_are_rejected() {
+    let mut job = PrintJob::new();
+    job.add_card(1, card_png([0x20, 0x40, 0x80, 0xff]));
+    for (width, height) in [
+        (0.0, 792.0),
+        (612.0, -792.0),
+        (f32::NAN, 792.0),
+        (612.0, f32::INFINITY),
+        (612.0, 20000.0),
+    ] {
+        assert!(job.set_custom_paper_size(width, height).is_err());
+    }
+    // The previous paper size is kept
+    let doc = Document::load_mem(&job.print().unwrap()).unwrap();
+    assert_eq!(pages(&doc)[0].size, (LETTER_WIDTH, LETTER_HEIGHT));
+
+    // Pages too small for a card are rejected when printing
+    job.set_custom_paper_size(144.0, 144.0).unwrap();
+    assert!(matches!(job.print(), Err(PrintError::Layout(_))));
+}
+
+#[test]
+fn card_images_are_embedded_once() {
+    let mut job = PrintJob::new();
+    job.add_card(12, card_png([0x20, 0x40, 0x80, 0xff]));
+    job.add_card(3, card_png([0x80, 0x40, 0x20, 0xff]));
+    let doc = Document::load_mem(&job.print().unwrap()).unwrap();
+
+    let pages = pages(&doc);
+    assert_eq!(pages.len(), 2);
+    assert_eq!(images(&doc).len(), 2);
+    let names = pages
+        .iter()
+        .flat_map(|page| page.placements.iter().map(|p| p.image.clone()))
+        .collect::<Vec<_>>();
+    assert_eq!(names.iter().filter(|name| *name == &names[0]).count(), 12);
+}
+
+#[test]
+fn jpeg_images_are_passed_through() {
+    let jpg = card_jpg();
+    let mut job = PrintJob::new();
+    job.add_card(2, jpg.clone());
+    let doc = Document::load_mem(&job.print().unwrap()).unwrap();
+
+    let images = images(&doc);
+    assert_eq!(images.len(), 1);
+    let image = images[0];
+    assert_eq!(
+        image.dict.get(b"Filter").unwrap().as_name().unwrap(),
+        b"DCTDecode"
+    );
+    assert_eq!(image.dict.get(b"Width").unwrap().as_i64().unwrap(), 745);
+    assert_eq!(image.dict.get(b"Height").unwrap().as_i64().unwrap(), 1040);
+    assert_eq!(image.content, jpg.to_vec());
+}
+
+#[cfg(feature = "parallel")]
+#[test]
+fn parallel_and_serial_pdfs_are_identical() {
+    let print = |threads| {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap();
+        // Jobs aren't `Send`, so each is created on the pool
+        pool.install(|| {
+            let mut job = PrintJob::new();
+            for i in 0..12 {
+                job.add_card(2, card_png([i * 20, 0x40, 0x80, 0xff]));
+            }
+            job.add_card(1, card_jpg());
+            job.add_card(1, Box::from(&b"not an image"[..]));
+            job.set_card_back(card_png([0x80, 0x40, 0x20, 0xff]));
+            job.set_bleed(BleedMode::Mirror, 1.0).unwrap();
+            job.set_skip_bad_cards(true);
+            job.set_creation_date(1_700_000_000.0);
+            let pdf = job.print().unwrap();
+            (pdf, job.skipped_cards())
+        })
+    };
+    let (serial, skipped) = print(1);
+    assert_eq!(skipped, [13]);
+    assert!(print(4) == (serial, skipped));
+}
+
+#[test]
+fn png_transparency_is_kept_as_soft_mask() {
+    let mut job = PrintJob::new();
+    job.add_card(1, card_png([0x20, 0x40, 0x80, 0x80]));
+    let doc = Document::load_mem(&job.print().unwrap()).unwrap();
+
+    let images = images(&doc);
+    assert_eq!(images.len(), 1);
+    let image = images[0];
+    let mask_id = image.dict.get(b"SMask").unwrap().as_reference().unwrap();
+    let mask = doc.get_object(mask_id).unwrap().as_stream().unwrap();
+    assert_eq!(
+        mask.dict.get(b"ColorSpace").unwrap().as_name().unwrap(),
+        b"DeviceGray"
+    );
+    assert_eq!(
+        image.dict.get(b"ColorSpace").unwrap().as_name().unwrap(),
+        b"DeviceRGB"
+    );
+}
+
+#[test]
+fn back_pages_are_mirrored_for_long_edge_duplex() {
+    let mut job = PrintJob::new();
+    job.add_double_sided_card(
+        2,
+        card_png([0x20, 0x40, 0x80, 0xff]),
+        card_png([0x80, 0x40, 0x20, 0xff]),
+    );
+    job.add_card(1, card_png([0x40, 0x80, 0x20, 0xff]));
+    job.set_back_offset(1.0, -2.0);
+    let doc = Document::load_mem(&job.print().unwrap()).unwrap();
+
+    let pages = pages(&doc);
+    assert_eq!(pages.len(), 2);
+    assert_eq!(pages[0].placements.len(), 3);
+    assert_eq!(pages[1].placements.len(), 2);
+
+    // Backs of the first row are drawn right to left
+    let mm = 72.0 / 25.4;
+    for (front, back) in pages[0].placements.iter().zip(&pages[1].placements) {
+        assert_ne!(front.image, back.image);
+        assert_close(
+            back.matrix[4],
+            LETTER_WIDTH - front.matrix[4] - CARD_WIDTH + 1.0 * mm,
+        );
+        assert_close(back.matrix[5], front.matrix[5] - 2.0 * mm);
+    }
+}
+
+#[test]
+fn card_back_is_printed_behind_every_card() {
+    let mut job = PrintJob::new();
+    job.add_card(10, card_png([0x20, 0x40, 0x80, 0xff]));
+    job.set_card_back(card_png([0x80, 0x40, 0x20, 0xff]));
+    job.set_duplex(Duplex::ShortEdge);
+    let doc = Document::load_mem(&job.print().unwrap()).unwrap();
+
+    let pages = pages(&doc);
+    assert_eq!(pages.len(), 4);
+    assert_eq!(pages[1].placements.len(), 9);
+    assert_eq!(pages[3].placements.len(), 1);
+    assert_eq!(images(&doc).len(), 2);
+
+    // Backs are mirrored top to bottom and rotated
+    let (front, back) = (&pages[2].placements[0], &pages[3].placements[0]);
+    assert_close(back.matrix[0], -CARD_WIDTH);
+    assert_close(back.matrix[3], -CARD_HEIGHT);
+    assert_close(back.matrix[4], front.matrix[4] + CARD_WIDTH);
+    assert_close(back.matrix[5], LETTER_HEIGHT - front.matrix[5]);
+}
+
+#[test]
+fn print_shop_bleed_spaces_cards_apart() {
+    let mut job = PrintJob::new();
+    job.add_card(2, card_jpg());
+    job.set_print_shop_bleed();
+    let doc = Document::load_mem(&job.print().unwrap()).unwrap();
+
+    // Bleed is generated, so the jpeg can't be passed through
+    let images = images(&doc);
+    assert_eq!(images.len(), 1);
+    assert_eq!(
+        images[0].dict.get(b"Filter").unwrap().as_name().unwrap(),
+        b"FlateDecode"
+    );
+    assert_eq!(
+        images[0].dict.get(b"Width").unwrap().as_i64().unwrap(),
+        745 + 2 * 35
+    );
+
+    let bleed = 35.0 * CARD_WIDTH / 745.0;
+    let placements = &pages(&doc)[0].placements;
+    assert_close(placements[0].matrix[0], CARD_WIDTH + 2.0 * bleed);
+    assert_close(placements[0].matrix[3], CARD_HEIGHT + 2.0 * bleed);
+    assert_close(
+        placements[1].matrix[4] - placements[0].matrix[4],
+        CARD_WIDTH + 2.0 * bleed,
+    );
+}
+
+#[test]
+fn cut_guides_are_drawn_as_vectors() {
+    let operators = |guides| {
+        let mut job = PrintJob::new();
+        job.add_card(4, card_png([0x20, 0x40, 0x80, 0xff]));
+        job.set_cut_guides(guides);
+        let doc = Document::load_mem(&job.print().unwrap()).unwrap();
+        let page_id = doc.page_iter().next().unwrap();
+        let content = Content::decode(&doc.get_page_content(page_id).unwrap()).unwrap();
+        content
+            .operations
+            .into_iter()
+            .map(|op| op.operator)
+            .collect::<Vec<_>>()
+    };
+    let count = |operators: &[String], operator: &str| {
+        operators.iter().filter(|op| *op == operator).count()
+    };
+
+    // Crosses at each of the 4 x 4 grid corners
+    let crop_marks = operators(CutGuides::CropMarks);
+    assert_eq!(count(&crop_marks, "m"), 32);
+    assert_eq!(count(&crop_marks, "S"), 1);
+
+    // Lines across the page are drawn before the cards
+    let cut_lines = operators(CutGuides::CutLines);
+    assert_eq!(count(&cut_lines, "d"), 1);
+    assert_eq!(count(&cut_lines, "m"), 8);
+    let first_stroke = cut_lines.iter().position(|op| op == "S").unwrap();
+    let first_image = cut_lines.iter().position(|op| op == "Do").unwrap();
+    assert!(first_stroke < first_image);
+
+    // An outline around each card, after the black bleed
+    let outlines = operators(CutGuides::Outlines);
+    assert_eq!(count(&outlines, "re"), 8);
+    assert_eq!(count(&outlines, "m"), 0);
+
+    let registration = operators(CutGuides::RegistrationMarks);
+    assert_eq!(count(&registration, "re"), 5);
+    assert_eq!(count(&registration, "m"), 2);
+
+    let none = operators(CutGuides::None);
+    assert_eq!(count(&none, "S"), 0);
+}
+
+#[test]
+fn gutter_separates_cards_and_their_crop_marks() {
+    let gutter = 3.0 * 72.0 / 25.4;
+    let mut job = PrintJob::new();
+    job.add_card(9, card_png([0x20, 0x40, 0x80, 0xff]));
+    job.set_gutter(3.0).unwrap();
+    assert_eq!(job.cards_per_page().unwrap(), 9);
+    let doc = Document::load_mem(&job.print().unwrap()).unwrap();
+
+    let placements = &pages(&doc)[0].placements;
+    assert_close(
+        placements[1].matrix[4] - placements[0].matrix[4],
+        CARD_WIDTH + gutter,
+    );
+    assert_close(
+        placements[0].matrix[5] - placements[3].matrix[5],
+        CARD_HEIGHT + gutter,
+    );
+    // The grid stays centered on the page
+    let right = placements[2].matrix[4] + CARD_WIDTH;
+    assert_close(placements[0].matrix[4], LETTER_WIDTH - right);
+
+    // Two marks at each corner of each card, none longer than the gutter
+    // between cards
+    let page_id = doc.page_iter().next().unwrap();
+    let content = Content::decode(&doc.get_page_content(page_id).unwrap()).unwrap();
+    let points = content
+        .operations
+        .iter()
+        .filter(|op| op.operator == "m" || op.operator == "l")
+        .map(|op| (to_f32(&op.operands[0]), to_f32(&op.operands[1])))
+        .collect::<Vec<_>>();
+    assert_eq!(points.len(), 9 * 8 * 2);
+    for line in points.chunks(2) {
+        let length = (line[1].0 - line[0].0).abs() + (line[1].1 - line[0].1).abs();
+        assert!(length <= 9.6 + 0.01);
+        let inside = placements.iter().any(|placement| {
+            let [width, _, _, height, x, y] = placement.matrix;
+            let (mx, my) = ((line[0].0 + line[1].0) / 2.0, (line[0].1 + line[1].1) / 2.0);
+            mx > x && mx < x + width && my > y && my < y + height
+        });
+        assert!(!inside, "crop mark {line:?} overlaps a card");
+    }
+
+    // Wider gutters fit fewer cards
+    job.set_gutter(10.0).unwrap();
+    assert_eq!(job.cards_per_page().unwrap(), 6);
+    assert!(job.set_gutter(-1.0).is_err());
+    assert!(job.set_gutter(11.0).is_err());
+}
+
+#[test]
+fn smaller_cards_fill_space_below_larger_ones() {
+    let token_width = 44.0 * 72.0 / 25.4;
+    let token_height = 63.0 * 72.0 / 25.4;
+    let mut job = PrintJob::new();
+    job.add_card(6, card_png([0x20, 0x40, 0x80, 0xff]));
+    job.add_card(6, card_png([0x80, 0x40, 0x20, 0xff]));
+    job.set_card_size(1, CardSize::Token).unwrap();
+    assert!(job.set_card_size(2, CardSize::Token).is_err());
+    let doc = Document::load_mem(&job.print().unwrap()).unwrap();
+
+    // Two rows of standard cards, then a row of four tokens
+    let pages = pages(&doc);
+    assert_eq!(pages.len(), 2);
+    assert_eq!(pages[0].placements.len(), 10);
+    assert_eq!(pages[1].placements.len(), 2);
+    let (cards, tokens) = pages[0].placements.split_at(6);
+    let lowest_card = cards.iter().map(|p| p.matrix[5]).fold(f32::MAX, f32::min);
+    for token in tokens {
+        assert_close(token.matrix[0], token_width);
+        assert_close(token.matrix[3], token_height);
+        assert!(token.matrix[5] + token_height < lowest_card);
+    }
+    // Both grids are centered on the page together
+    let top = cards[0].matrix[5] + CARD_HEIGHT;
+    assert_close(LETTER_HEIGHT - top, tokens[0].matrix[5]);
+
+    // The token image is resized to the token size once
+    let token_images = images(&doc)
+        .into_iter()
+        .filter(|image| image.dict.get(b"Width").unwrap().as_i64().unwrap() == 520)
+        .count();
+    assert_eq!(token_images, 1);
+}
+
+#[test]
+fn planes_are_rotated_to_landscape() {
+    let mut job = PrintJob::new();
+    job.add_card(3, card_png([0x20, 0x40, 0x80, 0xff]));
+    job.set_card_size(0, CardSize::Plane).unwrap();
+    let doc = Document::load_mem(&job.print().unwrap()).unwrap();
+
+    // Two planes fit on each page of letter paper
+    let pages = pages(&doc);
+    assert_eq!(pages.len(), 2);
+    assert_eq!(pages[0].placements.len(), 2);
+    let matrix = pages[0].placements[0].matrix;
+    assert_close(matrix[0], 152.4 * 72.0 / 25.4);
+    assert_close(matrix[3], 108.0 * 72.0 / 25.4);
+
+    let image = images(&doc)[0];
+    let width = image.dict.get(b"Width").unwrap().as_i64().unwrap();
+    let height = image.dict.get(b"Height").unwrap().as_i64().unwrap();
+    assert_eq!((width, height), (1802, 1276));
+}
+
+#[test]
+fn corners_are_filled_with_border_color() {
+    // White bordered card with a transparent top left corner
+    let mut image = RgbaImage::from_pixel(745, 1040, Rgba([0xff, 0xff, 0xff, 0xff]));
+    for x in 0..20 {
+        for y in 0..20 {
+            image.put_pixel(x, y, Rgba([0x00, 0x00, 0x00, 0x00]));
+        }
+    }
+
+    let mut job = PrintJob::new();
+    job.add_card(1, encode(image, ImageFormat::Png));
+    job.set_corner_mode(CornerMode::BorderColor);
+    let doc = Document::load_mem(&job.print().unwrap()).unwrap();
+
+    let images = images(&doc);
+    assert!(!images[0].dict.has(b"SMask"));
+    let pixels = pixels(images[0]);
+    assert_eq!(&pixels[..3], &[0xff, 0xff, 0xff]);
+}
+
+#[test]
+fn invalid_bleed_is_rejected() {
+    let mut job = PrintJob::new();
+    assert!(job.set_bleed(BleedMode::Mirror, -1.0).is_err());
+    assert!(job.set_bleed(BleedMode::Mirror, 20.0).is_err());
+    assert!(job.set_bleed(BleedMode::Mirror, 2.0).is_ok());
+}
+
+#[test]
+fn invalid_scale_is_rejected() {
+    let mut job = PrintJob::new();
+    assert!(job.set_scale(0.0).is_err());
+    assert!(job.set_scale(f32::NAN).is_err());
+}
+
+#[test]
+fn progress_is_reported_for_each_step() {
+    let mut job = PrintJob::new();
+    job.add_card(2, card_png([255, 0, 0, 255]));
+    job.add_card(9, card_png([0, 0, 255, 255]));
+    let events = Rc::new(RefCell::new(Vec::<Progress>::new()));
+    let handler_events = events.clone();
+    job.on_progress(move |progress| handler_events.borrow_mut().push(progress));
+    let pdf = job.print().unwrap();
+
+    let events = events.borrow();
+    let phases = events
+        .iter()
+        .map(|progress| (progress.phase, progress.current, progress.total))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        phases,
+        [
+            (Phase::Images, 1, 2),
+            (Phase::Images, 2, 2),
+            (Phase::Pages, 1, 2),
+            (Phase::Pages, 2, 2),
+            (Phase::Finishing, 0, 1),
+        ]
+    );
+    assert!(events.windows(2).all(|w| w[0].bytes <= w[1].bytes));
+    assert!(events.last().unwrap().bytes < pdf.len());
+    assert_eq!(events[2].to_string(), "Generating pages (1 / 2)");
+}
+
+#[test]
+fn job_can_be_cancelled_between_pages() {
+    let mut job = PrintJob::new();
+    job.add_card(27, card_png([255, 0, 0, 255]));
+    let token = job.cancel_token();
+    let pages = Rc::new(RefCell::new(0));
+    let handler_pages = pages.clone();
+    job.on_progress(move |progress| {
+        if progress.phase == Phase::Pages {
+            *handler_pages.borrow_mut() += 1;
+            token.cancel();
+        }
+    });
+    let err = job.print().unwrap_err();
+    assert!(err.to_string().contains("cancelled"));
+    assert_eq!(*pages.borrow(), 1);
+}
+
+#[test]
+fn bad_card_errors_name_the_card() {
+    let mut job = PrintJob::new();
+    job.add_card(1, card_png([255, 0, 0, 255]));
+    job.add_card(1, Box::from(&b"not an image"[..]));
+    let err = job.print().unwrap_err();
+    assert_eq!(err, PrintError::UnsupportedFormat { card: Some(1) });
+
+    let mut truncated = card_png([255, 0, 0, 255]).into_vec();
+    truncated.truncate(truncated.len() / 2);
+    let mut job = PrintJob::new();
+    job.add_card(1, truncated.into());
+    let err = job.print().unwrap_err();
+    assert!(matches!(err, PrintError::Decode { card: Some(0), .. }));
+
+    // Truncated jpegs are rejected whether they are passed through or decoded
+    let mut truncated = card_jpg().into_vec();
+    truncated.truncate(truncated.len() / 2);
+    let mut job = PrintJob::new();
+    job.add_card(1, card_jpg());
+    job.add_card(1, truncated.into());
+    let err = job.print().unwrap_err();
+    assert!(matches!(err, PrintError::Decode { card: Some(1), .. }));
+    job.set_dpi(150).unwrap();
+    let err = job.print().unwrap_err();
+    assert!(matches!(err, PrintError::Decode { card: Some(1), .. }));
+}
+
+#[test]
+fn bad_cards_can_be_replaced_with_placeholders() {
+    let mut job = PrintJob::new();
+    job.set_skip_bad_cards(true);
+    job.add_card(1, card_png([255, 0, 0, 255]));
+    job.add_card(2, Box::from(&b"not an image"[..]));
+    let pdf = job.print().unwrap();
+    assert_eq!(job.skipped_cards(), [1]);
+
+    let doc = Document::load_mem(&pdf).unwrap();
+    let page = &pages(&doc)[0];
+    assert_eq!(page.placements.len(), 3);
+    assert_eq!(images(&doc).len(), 2);
+}
+
+#[test]
+fn adjustments_are_applied_per_job_and_per_card() {
+    let mut job = PrintJob::new();
+    job.add_card(1, card_png([100, 100, 100, 255]));
+    job.add_card(1, card_png([100, 100, 100, 255]));
+    job.add_card(1, card_jpg());
+    let brighter = Adjustments {
+        brightness: 0.2,
+        ..Adjustments::new()
+    };
+    job.set_adjustments(&brighter).unwrap();
+    job.set_card_adjustments(1, &Adjustments::new()).unwrap();
+    let pdf = job.print().unwrap();
+
+    let doc = Document::load_mem(&pdf).unwrap();
+    let images = images(&doc);
+    assert_eq!(images.len(), 3);
+    // Adjusted jpegs are decoded instead of being passed through
+    assert!(
+        images
+            .iter()
+            .all(|image| image.dict.get(b"Filter").unwrap().as_name().unwrap() == b"FlateDecode")
+    );
+    let pixels = images.iter().map(|image| pixels(image)).collect::<Vec<_>>();
+    assert!(pixels.iter().any(|p| p.iter().all(|&p| p == 151)));
+    assert!(pixels.iter().any(|p| p.iter().all(|&p| p == 100)));
+}
+
+#[test]
+fn invalid_adjustments_are_rejected() {
+    let mut job = PrintJob::new();
+    let invalid = Adjustments {
+        gamma: 0.0,
+        ..Adjustments::new()
+    };
+    assert!(job.set_adjustments(&invalid).is_err());
+    assert!(job.set_card_adjustments(0, &Adjustments::new()).is_err());
+    assert!(job.set_adjustments(&Adjustments::print_friendly()).is_ok());
+}
+
+/// Minimal cmyk printer profile that prints lightness with black ink only
+fn cmyk_profile() -> Box<[u8]> {
+    let lut = |inputs: u8, outputs: u8, clut: Vec<u16>| {
+        LutWarehouse::Lut(LutDataType {
+            num_input_channels: inputs,
+            num_output_channels: outputs,
+            num_clut_grid_points: 2,
+            matrix: Matrix3d::IDENTITY,
+            num_input_table_entries: 2,
+            num_output_table_entries: 2,
+            input_table: LutStore::Store16([0, 0xffff].repeat(inputs as usize)),
+            clut_table: LutStore::Store16(clut),
+            output_table: LutStore::Store16([0, 0xffff].repeat(outputs as usize)),
+            lut_type: LutType::Lut16,
+        })
+    };
+    // Corners of the cmyk cube to lab, and of the lab cube to cmyk
+    let cmyk_to_lab = (0..16u32)
+        .flat_map(|i| {
+            let lightness = if i & 1 == 1 {
+                0.0
+            } else {
+                1.0 - (i >> 1).count_ones() as f32 / 4.0
+            };
+            [(lightness * 65280.0) as u16, 0x8080, 0x8080]
+        })
+        .collect::<Vec<_>>();
+    let lab_to_cmyk = (0..8u32)
+        .flat_map(|i| [0, 0, 0, if i >> 2 == 0 { 0xffff } else { 0 }])
+        .collect::<Vec<_>>();
+
+    let mut profile = ColorProfile::default();
+    profile.pcs = DataColorSpace::Lab;
+    profile.color_space = DataColorSpace::Cmyk;
+    profile.profile_class = ProfileClass::OutputDevice;
+    profile.white_point = Xyzd::new(0.9642, 1.0, 0.8249);
+    profile.lut_a_to_b_perceptual = Some(lut(4, 3, cmyk_to_lab));
+    profile.lut_b_to_a_perceptual = Some(lut(3, 4, lab_to_cmyk));
+    profile.encode().unwrap().into()
+}
+
+#[test]
+fn srgb_output_embeds_output_intent() {
+    let mut job = PrintJob::new();
+    job.add_card(1, card_png([255, 0, 0, 255]));
+    job.add_card(1, card_jpg());
+    job.set_color_mode(ColorMode::Srgb, None).unwrap();
+    let doc = Document::load_mem(&job.print().unwrap()).unwrap();
+
+    let intents = doc.catalog().unwrap().get(b"OutputIntents").unwrap();
+    let intent = intents.as_array().unwrap()[0].as_dict().unwrap();
+    assert_eq!(intent.get(b"S").unwrap().as_name().unwrap(), b"GTS_PDFX");
+    let profile_id = intent
+        .get(b"DestOutputProfile")
+        .unwrap()
+        .as_reference()
+        .unwrap();
+    let profile = doc.get_object(profile_id).unwrap().as_stream().unwrap();
+    assert_eq!(profile.dict.get(b"N").unwrap().as_i64().unwrap(), 3);
+
+    // Both the decoded png and the passed through jpeg use the profile
+    for image in images(&doc) {
+        let color_space = image.dict.get(b"ColorSpace").unwrap().as_array().unwrap();
+        assert_eq!(color_space[0].as_name().unwrap(), b"ICCBased");
+        assert_eq!(color_space[1].as_reference().unwrap(), profile_id);
+    }
+
+    // The document doesn't claim pdf/x conformance, since it uses soft masks
+    // and has no trim or bleed boxes
+    let info_id = doc.trailer.get(b"Info").unwrap().as_reference().unwrap();
+    let info = doc.get_dictionary(info_id).unwrap();
+    assert!(!info.has(b"GTS_PDFXVersion"));
+    assert!(!info.has(b"Trapped"));
+    for page_id in doc.page_iter() {
+        assert!(!doc.get_dictionary(page_id).unwrap().has(b"TrimBox"));
+    }
+}
+
+#[test]
+fn cmyk_output_converts_images_with_profile() {
+    let mut job = PrintJob::new();
+    job.add_card(1, card_png([255, 255, 255, 255]));
+    job.add_card(1, card_png([0, 0, 0, 255]));
+    job.set_color_mode(ColorMode::Cmyk, Some(cmyk_profile()))
+        .unwrap();
+    let doc = Document::load_mem(&job.print().unwrap()).unwrap();
+
+    let images = images(&doc);
+    assert_eq!(images.len(), 2);
+    let mut blacks = Vec::new();
+    for image in images {
+        let color_space = image.dict.get(b"ColorSpace").unwrap().as_name().unwrap();
+        assert_eq!(color_space, b"DeviceCMYK");
+        let pixels = pixels(image);
+        assert_eq!(pixels.len(), 745 * 1040 * 4);
+        assert!(pixels.chunks(4).all(|p| p[..3] == [0, 0, 0]));
+        blacks.push(pixels[3]);
+    }
+    blacks.sort();
+    assert_eq!(blacks, [0, 255]);
+
+    let intents = doc.catalog().unwrap().get(b"OutputIntents").unwrap();
+    let intent = intents.as_array().unwrap()[0].as_dict().unwrap();
+    let profile_id = intent
+        .get(b"DestOutputProfile")
+        .unwrap()
+        .as_reference()
+        .unwrap();
+    let profile = doc.get_object(profile_id).unwrap().as_stream().unwrap();
+    assert_eq!(profile.dict.get(b"N").unwrap().as_i64().unwrap(), 4);
+}
+
+#[test]
+fn cmyk_output_requires_cmyk_profile() {
+    let mut job = PrintJob::new();
+    assert!(job.set_color_mode(ColorMode::Cmyk, None).is_err());
+    let garbage = Box::from(&b"not a profile"[..]);
+    assert!(job.set_color_mode(ColorMode::Cmyk, Some(garbage)).is_err());
+    let srgb = ColorProfile::new_srgb().encode().unwrap().into();
+    assert!(job.set_color_mode(ColorMode::Cmyk, Some(srgb)).is_err());
+}
+
+#[test]
+fn grayscale_and_line_art_images_use_device_gray() {
+    for (mode, expected) in [
+        (RenderMode::Grayscale, [54, 182]),
+        (RenderMode::LineArt, [0, 255]),
+    ] {
+        let mut job = PrintJob::new();
+        job.add_card(1, card_png([255, 0, 0, 255]));
+        job.add_card(1, card_png([0, 255, 0, 255]));
+        job.set_render_mode(mode);
+        let doc = Document::load_mem(&job.print().unwrap()).unwrap();
+
+        let mut levels = Vec::new();
+        for image in images(&doc) {
+            let color_space = image.dict.get(b"ColorSpace").unwrap().as_name().unwrap();
+            assert_eq!(color_space, b"DeviceGray");
+            let pixels = pixels(image);
+            assert_eq!(pixels.len(), 745 * 1040);
+            levels.push(pixels[0]);
+        }
+        levels.sort();
+        assert_eq!(levels, expected, "{mode:?}");
+    }
+}
+
+#[test]
+fn thin_border_replaces_frame_with_white() {
+    // Red card with a 30 pixel black frame
+    let image = RgbaImage::from_fn(745, 1040, |x, y| {
+        let edge = x.min(y).min(744 - x).min(1039 - y);
+        if edge < 30 {
+            Rgba([0, 0, 0, 255])
+        } else {
+            Rgba([255, 0, 0, 255])
+        }
+    });
+
+    let mut job = PrintJob::new();
+    job.add_card(1, encode(image, ImageFormat::Png));
+    job.set_thin_border(true);
+    let doc = Document::load_mem(&job.print().unwrap()).unwrap();
+
+    let pixels = pixels(images(&doc)[0]);
+    let pixel = |x: usize, y: usize| &pixels[(y * 745 + x) * 3..][..3];
+    assert_eq!(pixel(0, 500), [0, 0, 0]);
+    assert_eq!(pixel(15, 500), [255, 255, 255]);
+    assert_eq!(pixel(372, 15), [255, 255, 255]);
+    assert_eq!(pixel(100, 500), [255, 0, 0]);
+
+    // No black bleed is drawn behind the card
+    let page = doc.page_iter().next().unwrap();
+    let content = Content::decode(&doc.get_page_content(page).unwrap()).unwrap();
+    assert!(!content.operations.iter().any(|op| op.operator == "re"));
+}
+
+/// Streams in the document with the given subtype
+fn streams_of<'a>(doc: &'a Document, subtype: &[u8]) -> Vec<&'a Stream> {
+    doc.objects
+        .values()
+        .filter_map(|object| object.as_stream().ok())
+        .filter(|stream| {
+            stream.dict.get(b"Subtype").and_then(Object::as_name).ok() == Some(subtype)
+        })
+        .collect()
+}
+
+#[test]
+fn text_cards_are_drawn_with_embedded_font() {
+    let mut card = TextCard::new("Lightning Bolt".to_string());
+    card.mana_cost = Some("{R}".to_string());
+    card.type_line = Some("Instant".to_string());
+    card.oracle_text = Some("Lightning Bolt deals 3 damage to any target.".to_string());
+    let mut creature = TextCard::new("Grizzly Bears".to_string());
+    creature.mana_cost = Some("{1}{G}".to_string());
+    creature.power = Some("2".to_string());
+    creature.toughness = Some("2".to_string());
+
+    let mut job = PrintJob::new();
+    job.add_text_card(2, &card);
+    job.add_text_card(1, &creature);
+    job.add_card(1, card_png([255, 0, 0, 255]));
+    let doc = Document::load_mem(&job.print().unwrap()).unwrap();
+
+    // Text cards are forms drawn in place of images
+    assert_eq!(images(&doc).len(), 1);
+    let forms = streams_of(&doc, b"Form");
+    assert_eq!(forms.len(), 2);
+    assert_eq!(pages(&doc)[0].placements.len(), 4);
+    for form in &forms {
+        let content = Content::decode(&form.decompressed_content().unwrap()).unwrap();
+        assert!(content.operations.iter().any(|op| op.operator == "Tj"));
+    }
+
+    // A single font is shared by every card, with its glyphs mapped back to
+    // text
+    let fonts = doc
+        .objects
+        .values()
+        .filter_map(|object| object.as_dict().ok())
+        .filter(|dict| dict.get(b"Subtype").and_then(Object::as_name).ok() == Some(b"Type0"))
+        .collect::<Vec<_>>();
+    assert_eq!(fonts.len(), 1);
+    let to_unicode = doc
+        .get_object(fonts[0].get(b"ToUnicode").unwrap().as_reference().unwrap())
+        .and_then(Object::as_stream)
+        .unwrap();
+    let cmap = String::from_utf8(to_unicode.decompressed_content().unwrap()).unwrap();
+    for c in "LBGI/2".chars() {
+        assert!(cmap.contains(&format!("<{:04X}>\n", c as u32)), "{c}");
+    }
+
+    // Only the outlines of used glyphs are embedded, a fraction of the
+    // full font
+    let descriptor = doc
+        .objects
+        .values()
+        .filter_map(|object| object.as_dict().ok())
+        .find(|dict| dict.get(b"FontFile2").is_ok())
+        .unwrap();
+    let file = doc
+        .get_object(
+            descriptor
+                .get(b"FontFile2")
+                .unwrap()
+                .as_reference()
+                .unwrap(),
+        )
+        .and_then(Object::as_stream)
+        .unwrap();
+    let data = file.decompressed_content().unwrap();
+    assert!(data.len() < 64_000, "{} bytes", data.len());
+    assert_eq!(
+        to_f32(file.dict.get(b"Length1").unwrap()),
+        data.len() as f32
+    );
+    let face = ttf_parser::Face::parse(&data, 0).unwrap();
+    let has_outline = |c| {
+        let glyph = face.glyph_index(c).unwrap();
+        face.glyph_bounding_box(glyph).is_some()
+    };
+    assert!(has_outline('L') && has_outline('y'));
+    assert!(!has_outline('Q'));
+}
+
+#[test]
+fn text_cards_need_no_images() {
+    let mut job = PrintJob::new();
+    job.add_double_sided_text_card(
+        1,
+        &TextCard::new("Delver of Secrets".to_string()),
+        &TextCard::new("Insectile Aberration".to_string()),
+    );
+    let doc = Document::load_mem(&job.print().unwrap()).unwrap();
+
+    assert!(images(&doc).is_empty());
+    let pages = pages(&doc);
+    assert_eq!(pages.len(), 2);
+    assert_eq!(pages[1].placements.len(), 1);
+}
+
+/// Title of an outline item
+fn title(item: &lopdf::Dictionary) -> Vec<u8> {
+    item.get(b"Title").unwrap().as_str().unwrap().to_vec()
+}
+
+#[test]
+fn document_has_metadata_and_outline() {
+    let mut job = PrintJob::new();
+    job.add_card(10, card_png([0x20, 0x40, 0x80, 0xff]));
+    job.add_card(3, card_png([0x80, 0x40, 0x20, 0xff]));
+    job.set_card_name(0, "Ledger Shredder".to_string()).unwrap();
+    job.set_card_name(1, "Lim-Dûl's Vault".to_string()).unwrap();
+    assert!(job.set_card_name(2, "Island".to_string()).is_err());
+    job.set_title("Izzet Tempo".to_string());
+    job.set_creation_date(1_700_000_000.0);
+    let doc = Document::load_mem(&job.print().unwrap()).unwrap();
+
+    let info_id = doc.trailer.get(b"Info").unwrap().as_reference().unwrap();
+    let info = doc.get_dictionary(info_id).unwrap();
+    let text = |key: &[u8]| info.get(key).unwrap().as_str().unwrap().to_vec();
+    assert_eq!(text(b"Title"), b"Izzet Tempo");
+    assert_eq!(text(b"Creator"), b"mtg-print");
+    assert_eq!(text(b"CreationDate"), b"D:20231114221320Z");
+
+    // An entry for each page, with the cards on it below
+    let catalog = doc.catalog().unwrap();
+    let outlines = catalog.get(b"Outlines").unwrap().as_reference().unwrap();
+    let outlines = doc.get_dictionary(outlines).unwrap();
+    assert_eq!(outlines.get(b"Count").unwrap().as_i64().unwrap(), 2);
+    let first = outlines.get(b"First").unwrap().as_reference().unwrap();
+    let last = outlines.get(b"Last").unwrap().as_reference().unwrap();
+    let first = doc.get_dictionary(first).unwrap();
+    let last = doc.get_dictionary(last).unwrap();
+    assert_eq!(title(first), b"Page 1");
+    assert_eq!(title(last), b"Page 2");
+
+    let page_ids = doc.page_iter().collect::<Vec<_>>();
+    let dest = last.get(b"Dest").unwrap().as_array().unwrap();
+    assert_eq!(dest[0].as_reference().unwrap(), page_ids[1]);
+    assert_eq!(last.get(b"Count").unwrap().as_i64().unwrap(), -2);
+    let card = last.get(b"First").unwrap().as_reference().unwrap();
+    let card = doc.get_dictionary(card).unwrap();
+    assert_eq!(title(card), b"1 Ledger Shredder");
+    let card = card.get(b"Next").unwrap().as_reference().unwrap();
+    let card = doc.get_dictionary(card).unwrap();
+    // Names outside of ascii are encoded as utf-16
+    let mut expected = vec![0xfe, 0xff];
+    for unit in "3 Lim-Dûl's Vault".encode_utf16() {
+        expected.extend(unit.to_be_bytes());
+    }
+    assert_eq!(title(card), expected);
+}
+
+#[test]
+fn footer_is_printed_below_the_cards() {
+    let mut job = PrintJob::new();
+    job.add_card(9, card_png([0x20, 0x40, 0x80, 0xff]));
+    job.set_card_back(card_png([0x80, 0x40, 0x20, 0xff]));
+    job.set_paper_size(PaperSize::A4);
+    job.set_footer(true);
+    let doc = Document::load_mem(&job.print().unwrap()).unwrap();
+
+    // Text is only drawn on the front, below the lowest card
+    let page_ids = doc.page_iter().collect::<Vec<_>>();
+    assert_eq!(page_ids.len(), 2);
+    let positions = |page_id| {
+        let content = Content::decode(&doc.get_page_content(page_id).unwrap()).unwrap();
+        content
+            .operations
+            .into_iter()
+            .filter(|op| op.operator == "Td")
+            .map(|op| to_f32(&op.operands[1]))
+            .collect::<Vec<_>>()
+    };
+    let front = positions(page_ids[0]);
+    assert_eq!(front.len(), 1);
+    let lowest_card = (841.89 - 3.0 * CARD_HEIGHT) / 2.0;
+    assert!(front[0] >= 18.0 && front[0] + 6.0 <= lowest_card - 9.6);
+    assert!(positions(page_ids[1]).is_empty());
+    let page = doc.get_dictionary(page_ids[0]).unwrap();
+    let resources = page.get(b"Resources").unwrap().as_dict().unwrap();
+    assert!(resources.get(b"Font").is_ok());
+
+    // Back pages are labelled after their front
+    let labels = doc.catalog().unwrap().get(b"PageLabels").unwrap();
+    let nums = labels.as_dict().unwrap().get(b"Nums").unwrap();
+    assert_eq!(nums.as_array().unwrap().len(), 4);
+}
+
+#[test]
+fn footer_is_skipped_without_room_above_the_page_margin() {
+    // The cut guides below the cards end 12 points from the bottom of letter
+    // paper, too close to the edge to print on
+    let mut job = PrintJob::new();
+    job.add_card(9, card_png([0x20, 0x40, 0x80, 0xff]));
+    job.set_footer(true);
+    let doc = Document::load_mem(&job.print().unwrap()).unwrap();
+    let page_id = doc.page_iter().next().unwrap();
+    let content = Content::decode(&doc.get_page_content(page_id).unwrap()).unwrap();
+    assert!(!content.operations.iter().any(|op| op.operator == "Tj"));
+    let page = doc.get_dictionary(page_id).unwrap();
+    let resources = page.get(b"Resources").unwrap().as_dict().unwrap();
+    assert!(resources.get(b"Font").is_err());
+}
+
+/// Names and contents of the files in a zip written without compression
+fn zip_files(data: &[u8]) -> Vec<(String, Vec<u8>)> {
+    let u16_at = |at: usize| u16::from_le_bytes([data[at], data[at + 1]]) as usize;
+    let mut files = Vec::new();
+    let mut at = 0;
+    while data[at..].starts_with(b"PK\x03\x04") {
+        let size = u32::from_le_bytes(data[at + 18..at + 22].try_into().unwrap()) as usize;
+        let name_length = u16_at(at + 26);
+        let start = at + 30 + name_length + u16_at(at + 28);
+        let name = String::from_utf8(data[at + 30..at + 30 + name_length].to_vec()).unwrap();
+        files.push((name, data[start..start + size].to_vec()));
+        at = start + size;
+    }
+    files
+}
+
+#[test]
+fn card_images_are_exported_with_bleed() {
+    let mut job = PrintJob::new();
+    job.add_card(2, card_png([0x20, 0x40, 0x80, 0xff]));
+    job.add_card(1, card_png([0x80, 0x40, 0x20, 0xff]));
+    job.add_double_sided_card(
+        1,
+        card_png([0x20, 0x80, 0x40, 0xff]),
+        card_png([0x40, 0x80, 0x20, 0xff]),
+    );
+    job.set_card_name(0, "Ledger Shredder".to_string()).unwrap();
+    job.set_card_name(2, "Delver of Secrets".to_string())
+        .unwrap();
+    job.set_card_back(card_png([0x10, 0x10, 0x10, 0xff]));
+    job.set_bleed(BleedMode::Mirror, 3.0).unwrap();
+    job.set_output_format(OutputFormat::CardImages);
+    job.set_dpi(254).unwrap();
+    let files = zip_files(&job.print().unwrap());
+
+    let names: Vec<_> = files.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(
+        names,
+        [
+            "001 Ledger Shredder.png",
+            "card back.png",
+            "002 Card 2.png",
+            "003 Delver of Secrets.png",
+            "003 Delver of Secrets (back).png",
+        ]
+    );
+    // 63 by 88mm plus 35 pixels of mirrored bleed on each side, just under
+    // 3mm, at 10 pixels per mm
+    let image = image::load_from_memory(&files[0].1).unwrap().to_rgba8();
+    assert_eq!(image.dimensions(), (689, 939));
+    assert_eq!(image.get_pixel(0, 0).0, [0x20, 0x40, 0x80, 0xff]);
+
+    // Black bleed is drawn around the card
+    job.set_bleed(BleedMode::Black, 1.0).unwrap();
+    let files = zip_files(&job.print().unwrap());
+    let image = image::load_from_memory(&files[0].1).unwrap().to_rgba8();
+    assert_eq!(image.dimensions(), (650, 900));
+    assert_eq!(image.get_pixel(325, 450).0, [0x20, 0x40, 0x80, 0xff]);
+    assert_eq!(image.get_pixel(0, 0).0, [0, 0, 0, 0xff]);
+
+    // Rounding can leave less bleed above and below than at the sides, with
+    // the card still centered
+    job.set_dpi(150).unwrap();
+    let files = zip_files(&job.print().unwrap());
+    let image = image::load_from_memory(&files[0].1).unwrap().to_rgba8();
+    assert_eq!(image.dimensions(), (384, 531));
+    assert_eq!(image.get_pixel(192, 4).0, [0, 0, 0, 0xff]);
+    assert_eq!(image.get_pixel(192, 5).0, [0x20, 0x40, 0x80, 0xff]);
+    assert_eq!(image.get_pixel(5, 265).0, [0, 0, 0, 0xff]);
+    assert_eq!(image.get_pixel(6, 265).0, [0x20, 0x40, 0x80, 0xff]);
+}
+
+#[test]
+fn sheets_are_rendered_at_chosen_dpi() {
+    let mut job = PrintJob::new();
+    job.add_card(10, card_png([0x20, 0x40, 0x80, 0xff]));
+    job.set_dpi(100).unwrap();
+    assert!(job.set_dpi(10).is_err());
+
+    job.set_output_format(OutputFormat::PngSheets);
+    let files = zip_files(&job.print().unwrap());
+    assert_eq!(files.len(), 2);
+    assert_eq!(files[0].0, "page-001.png");
+    let page = image::load_from_memory(&files[0].1).unwrap().to_rgb8();
+    assert_eq!(page.dimensions(), (850, 1100));
+    assert_eq!(page.get_pixel(425, 550).0, [0x20, 0x40, 0x80]);
+    assert_eq!(page.get_pixel(2, 2).0, [0xff, 0xff, 0xff]);
+    // The resolution is recorded so sheets print at their physical size
+    let png = png::Decoder::new(Cursor::new(&files[0].1))
+        .read_info()
+        .unwrap();
+    let dims = png.info().pixel_dims.unwrap();
+    assert_eq!((dims.xppu, dims.yppu), (3937, 3937));
+    assert!(matches!(dims.unit, png::Unit::Meter));
+
+    job.set_output_format(OutputFormat::TiffSheets);
+    let files = zip_files(&job.print().unwrap());
+    assert_eq!(files[1].0, "page-002.tiff");
+    let page = image::load_from_memory_with_format(&files[1].1, ImageFormat::Tiff).unwrap();
+    assert_eq!((page.width(), page.height()), (850, 1100));
+    let mut tiff = tiff::decoder::Decoder::new(Cursor::new(&files[1].1)).unwrap();
+    let resolution = tiff.get_tag(tiff::tags::Tag::XResolution).unwrap();
+    assert!(matches!(
+        resolution,
+        tiff::decoder::ifd::Value::Rational(100, 1)
+    ));
+    assert_eq!(
+        tiff.get_tag_u32(tiff::tags::Tag::ResolutionUnit).unwrap(),
+        2
+    );
+
+    job.set_output_format(OutputFormat::JpegSheets);
+    let files = zip_files(&job.print().unwrap());
+    assert_eq!(files[0].0, "page-001.jpg");
+    let page = image::load_from_memory_with_format(&files[0].1, ImageFormat::Jpeg).unwrap();
+    assert_eq!((page.width(), page.height()), (850, 1100));
+}
+
+#[test]
+fn svg_sheets_have_cut_paths_on_fronts() {
+    let mut job = PrintJob::new();
+    job.add_card(4, card_png([0x20, 0x40, 0x80, 0xff]));
+    job.add_card(1, card_png([0x80, 0x40, 0x20, 0xff]));
+    job.set_card_back(card_png([0x10, 0x10, 0x10, 0xff]));
+    job.set_output_format(OutputFormat::SvgSheets);
+    let files = zip_files(&job.print().unwrap());
+
+    let names: Vec<_> = files.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(names, ["page-001.svg", "page-001-back.svg"]);
+    let front = String::from_utf8(files[0].1.clone()).unwrap();
+    assert_eq!(front.matches("<image ").count(), 2);
+    assert_eq!(front.matches("<use ").count(), 5);
+    let cut_paths = &front[front.find("id=\"cut-paths\"").unwrap()..];
+    assert_eq!(cut_paths.matches("<rect ").count(), 5);
+    let back = String::from_utf8(files[1].1.clone()).unwrap();
+    assert!(!back.contains("cut-paths"));
+}
+
+#[test]
+fn text_cards_can_only_be_printed_as_pdf() {
+    let mut job = PrintJob::new();
+    job.add_text_card(1, &TextCard::new("Llanowar Elves".to_string()));
+    job.set_output_format(OutputFormat::PngSheets);
+    let err = job.print().unwrap_err();
+    assert!(matches!(err, PrintError::UnsupportedOutput(_)));
+}
+
+fn image_size(image: &Stream) -> (i64, i64) {
+    let width = image.dict.get(b"Width").unwrap().as_i64().unwrap();
+    let height = image.dict.get(b"Height").unwrap().as_i64().unwrap();
+    (width, height)
+}
+
+#[test]
+fn card_images_are_resampled_to_dpi() {
+    let mut job = PrintJob::new();
+    job.add_card(1, card_png([0x20, 0x40, 0x80, 0xff]));
+    job.set_dpi(150).unwrap();
+    let doc = Document::load_mem(&job.print().unwrap()).unwrap();
+    assert_eq!(image_size(images(&doc)[0]), (373, 520));
+
+    job.set_dpi(600).unwrap();
+    job.set_resample_filter(ResampleFilter::Lanczos3);
+    let doc = Document::load_mem(&job.print().unwrap()).unwrap();
+    assert_eq!(image_size(images(&doc)[0]), (1490, 2080));
+
+    // Jpegs are only passed through at their own resolution
+    let filter = |image: &Stream| {
+        image
+            .dict
+            .get(b"Filter")
+            .unwrap()
+            .as_name()
+            .unwrap()
+            .to_vec()
+    };
+    let mut job = PrintJob::new();
+    job.add_card(1, card_jpg());
+    job.set_dpi(150).unwrap();
+    let doc = Document::load_mem(&job.print().unwrap()).unwrap();
+    assert_eq!(image_size(images(&doc)[0]), (373, 520));
+    assert_eq!(filter(images(&doc)[0]), b"FlateDecode");
+
+    job.set_dpi(300).unwrap();
+    let doc = Document::load_mem(&job.print().unwrap()).unwrap();
+    assert_eq!(image_size(images(&doc)[0]), (745, 1040));
+    assert_eq!(filter(images(&doc)[0]), b"DCTDecode");
+
+    job.set_dpi(600).unwrap();
+    job.set_resample_filter(ResampleFilter::Native);
+    let doc = Document::load_mem(&job.print().unwrap()).unwrap();
+    assert_eq!(image_size(images(&doc)[0]), (745, 1040));
+    assert_eq!(filter(images(&doc)[0]), b"DCTDecode");
+}
+
+#[test]
+fn native_resolution_keeps_image_size() {
+    let image = RgbaImage::from_pixel(1000, 1400, Rgba([0x20, 0x40, 0x80, 0xff]));
+    let mut job = PrintJob::new();
+    job.add_card(1, encode(image, ImageFormat::Png));
+    job.set_resample_filter(ResampleFilter::Native);
+    let doc = Document::load_mem(&job.print().unwrap()).unwrap();
+    assert_eq!(image_size(images(&doc)[0]), (1000, 1400));
+    // The pdf scales the image to the card
+    let matrix = pages(&doc)[0].placements[0].matrix;
+    assert_close(matrix[0], CARD_WIDTH);
+    assert_close(matrix[3], CARD_HEIGHT);
+
+    // Bleed is generated at the resolution of the image
+    job.set_bleed(BleedMode::Mirror, 3.0).unwrap();
+    let doc = Document::load_mem(&job.print().unwrap()).unwrap();
+    assert_eq!(image_size(images(&doc)[0]), (1094, 1494));
+}
+
+#[test]
+fn text_stamp_is_drawn_on_every_card_front() {
+    let mut job = PrintJob::new();
+    job.add_card(3, card_png([0x20, 0x40, 0x80, 0xff]));
+    job.set_card_back(card_png([0x80, 0x40, 0x20, 0xff]));
+    job.set_stamp_text("PROXY".to_string());
+    job.set_stamp_style(StampPosition::TextBox, 0.4, 90.0)
+        .unwrap();
+    let doc = Document::load_mem(&job.print().unwrap()).unwrap();
+
+    let page_ids = doc.page_iter().collect::<Vec<_>>();
+    let stamps = |page_id| {
+        let content = Content::decode(&doc.get_page_content(page_id).unwrap()).unwrap();
+        let operations = content.operations;
+        (operations.windows(2))
+            .filter(|ops| ops[1].operator == "gs")
+            .map(|ops| std::array::from_fn::<_, 6, _>(|i| to_f32(&ops[0].operands[i])))
+            .collect::<Vec<_>>()
+    };
+    let front = stamps(page_ids[0]);
+    assert_eq!(front.len(), 3);
+    assert!(stamps(page_ids[1]).is_empty());
+
+    // Rotated a quarter turn over the text box of the first card
+    let [a, b, c, d, e, f] = front[0];
+    assert_close(a, 0.0);
+    assert_close(d, 0.0);
+    assert!(b > 0.0 && c < 0.0);
+    let left = (LETTER_WIDTH - 3.0 * CARD_WIDTH) / 2.0;
+    let top = (LETTER_HEIGHT + 3.0 * CARD_HEIGHT) / 2.0;
+    assert_close(e, left + CARD_WIDTH / 2.0);
+    assert_close(f, top - CARD_HEIGHT * 0.76);
+
+    let page = doc.get_dictionary(page_ids[0]).unwrap();
+    let resources = page.get(b"Resources").unwrap().as_dict().unwrap();
+    assert!(resources.get(b"Font").is_ok());
+    let states = resources.get(b"ExtGState").unwrap().as_dict().unwrap();
+    let state = states.get(b"Stamp").unwrap().as_dict().unwrap();
+    assert_close(to_f32(state.get(b"ca").unwrap()), 0.4);
+
+    assert!(
+        job.set_stamp_style(StampPosition::Center, 1.5, 0.0)
+            .is_err()
+    );
+}
+
+#[test]
+fn logo_stamp_is_embedded_once() {
+    let mut job = PrintJob::new();
+    job.add_card(10, card_png([0x20, 0x40, 0x80, 0xff]));
+    assert!(job.set_stamp_logo(Box::from(&b"not an image"[..])).is_err());
+    job.set_stamp_logo(card_png([0xff, 0xff, 0xff, 0x80]))
+        .unwrap();
+    let doc = Document::load_mem(&job.print().unwrap()).unwrap();
+
+    assert_eq!(images(&doc).len(), 2);
+    let pages = pages(&doc);
+    assert_eq!(pages.len(), 2);
+    let stamps = pages[1]
+        .placements
+        .iter()
+        .filter(|placement| placement.image == b"Stamp")
+        .count();
+    assert_eq!(stamps, 1);
+
+    // Sheets have the logo drawn into the middle of each card, lightening it
+    job.set_output_format(OutputFormat::PngSheets);
+    job.set_dpi(100).unwrap();
+    let files = zip_files(&job.print().unwrap());
+    let page = image::load_from_memory(&files[0].1).unwrap().to_rgb8();
+    let center = page.get_pixel(425, 550).0;
+    assert!((0..3).all(|c| center[c] > [0x20, 0x40, 0x80][c] + 0x10));
+    assert_eq!(page.get_pixel(425, 450).0, [0x20, 0x40, 0x80]);
+}
+
+/// First card image exported by a job, without bleed
+fn exported_card(job: &mut PrintJob) -> RgbaImage {
+    job.set_bleed(BleedMode::Extend, 0.0).unwrap();
+    job.set_output_format(OutputFormat::CardImages);
+    let files = zip_files(&job.print().unwrap());
+    image::load_from_memory(&files[0].1).unwrap().to_rgba8()
+}
+
+fn assert_color(pixel: &Rgba<u8>, expected: [u8; 3]) {
+    assert!(
+        (0..3).all(|c| pixel[c].abs_diff(expected[c]) <= 8),
+        "expected {expected:?}, got {pixel:?}"
+    );
+}
+
+#[test]
+fn stamps_are_drawn_into_exported_card_fronts() {
+    let mut job = PrintJob::new();
+    job.add_card(2, card_png([0x20, 0x40, 0x80, 0xff]));
+    job.set_card_back(card_png([0x80, 0x40, 0x20, 0xff]));
+    job.set_stamp_text("PROXY".to_string());
+    job.set_stamp_style(StampPosition::Center, 1.0, 0.0)
+        .unwrap();
+    let is_stamp = |pixel: &Rgba<u8>| (0..3).all(|c| pixel[c].abs_diff(26) <= 8);
+
+    // Dark gray text across the middle of the front, which is left alone
+    // above and below it
+    let card = exported_card(&mut job);
+    assert!((0..744).any(|x| is_stamp(card.get_pixel(x, 520))));
+    assert!(!(0..744).any(|x| is_stamp(card.get_pixel(x, 300))));
+    assert_eq!(card.get_pixel(372, 100).0, [0x20, 0x40, 0x80, 0xff]);
+
+    // Turned a quarter, the text runs up the middle of the card
+    job.set_stamp_style(StampPosition::Center, 1.0, 90.0)
+        .unwrap();
+    let card = exported_card(&mut job);
+    assert!((0..744).any(|x| is_stamp(card.get_pixel(x, 300))));
+    assert!(!(0..200).any(|x| is_stamp(card.get_pixel(x, 520))));
+
+    // Backs aren't stamped
+    let files = zip_files(&job.print().unwrap());
+    assert_eq!(files[1].0, "card back.png");
+    let back = image::load_from_memory(&files[1].1).unwrap().to_rgba8();
+    assert!(
+        back.pixels()
+            .all(|pixel| pixel.0 == [0x80, 0x40, 0x20, 0xff])
+    );
+
+    job.set_output_format(OutputFormat::PngSheets);
+    job.set_dpi(100).unwrap();
+    let files = zip_files(&job.print().unwrap());
+    let front = image::load_from_memory(&files[0].1).unwrap().to_rgba8();
+    assert!(front.pixels().any(is_stamp));
+    let back = image::load_from_memory(&files[1].1).unwrap().to_rgba8();
+    assert!(!back.pixels().any(is_stamp));
+
+    // Svg sheets embed the stamped card images
+    job.set_output_format(OutputFormat::SvgSheets);
+    let stamped = zip_files(&job.print().unwrap());
+    job.set_stamp_text(String::new());
+    let plain = zip_files(&job.print().unwrap());
+    assert_ne!(stamped[0].1, plain[0].1);
+    assert_eq!(stamped[1].1, plain[1].1);
+}
+
+#[test]
+fn fit_modes_keep_aspect_ratio() {
+    // Square red image with a green frame
+    let image = RgbImage::from_fn(1000, 1000, |x, y| {
+        if x.min(y).min(999 - x).min(999 - y) < 40 {
+            Rgb([0x00, 0xff, 0x00])
+        } else {
+            Rgb([0xff, 0x00, 0x00])
+        }
+    });
+    let mut job = PrintJob::new();
+    job.add_card(1, encode(image, ImageFormat::Png));
+
+    let card = exported_card(&mut job);
+    assert_eq!(card.dimensions(), (744, 1039));
+    assert_color(card.get_pixel(372, 100), [0xff, 0x00, 0x00]);
+    assert_color(card.get_pixel(5, 520), [0x00, 0xff, 0x00]);
+
+    // The image is extended above and below with its border color
+    job.set_fit_mode(FitMode::Fit);
+    let card = exported_card(&mut job);
+    assert_eq!(card.dimensions(), (744, 1039));
+    assert_color(card.get_pixel(372, 100), [0x00, 0xff, 0x00]);
+    assert_color(card.get_pixel(372, 520), [0xff, 0x00, 0x00]);
+
+    // The frame is cropped off the sides
+    job.set_fit_mode(FitMode::Fill);
+    let card = exported_card(&mut job);
+    assert_eq!(card.dimensions(), (744, 1039));
+    assert_color(card.get_pixel(5, 520), [0xff, 0x00, 0x00]);
+    assert_color(card.get_pixel(372, 5), [0x00, 0xff, 0x00]);
+}
+
+#[test]
+fn scanned_cards_are_straightened_and_cropped() {
+    // Blue card tilted by 4 degrees on a light gray scanner bed
+    let (sin, cos) = 4f32.to_radians().sin_cos();
+    let scan = RgbImage::from_fn(1200, 1300, |x, y| {
+        let (x, y) = (x as f32 - 600.0, y as f32 - 650.0);
+        let (u, v) = (x * cos + y * sin, y * cos - x * sin);
+        if u.abs() < 315.0 && v.abs() < 440.0 {
+            Rgb([0x20, 0x40, 0x80])
+        } else {
+            Rgb([0xe0, 0xe0, 0xe0])
+        }
+    });
+    let mut job = PrintJob::new();
+    job.add_card(1, encode(scan, ImageFormat::Png));
+
+    let card = exported_card(&mut job);
+    assert_color(card.get_pixel(2, 2), [0xe0, 0xe0, 0xe0]);
+
+    job.set_auto_crop(true);
+    let card = exported_card(&mut job);
+    assert_eq!(card.dimensions(), (744, 1039));
+    for (x, y) in [(2, 2), (741, 2), (2, 1036), (741, 1036), (372, 520)] {
+        assert_color(card.get_pixel(x, y), [0x20, 0x40, 0x80]);
+    }
+
+    // Card images that are already cropped are left as is
+    let mut job = PrintJob::new();
+    job.add_card(1, card_png([0x20, 0x40, 0x80, 0xff]));
+    job.set_auto_crop(true);
+    assert_eq!(exported_card(&mut job).dimensions(), (744, 1039));
+}